@@ -0,0 +1,63 @@
+use anchor_lang::prelude::*;
+
+use crate::{assert_has_authority, state::{Config, PAUSE_WITHDRAW}};
+use crate::errors::AmmError;
+use crate::events::{AuthorityAccepted, AuthorityProposed, AuthorityRenounced};
+
+#[derive(Accounts)]
+pub struct SetAuthority<'info> {
+    user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Account<'info, Config>,
+}
+
+impl<'info> SetAuthority<'info> {
+    pub fn propose_authority(&mut self, new_authority: Pubkey) -> Result<()> {
+        assert_has_authority!(self);
+        self.config.pending_authority = Some(new_authority);
+
+        emit!(AuthorityProposed {
+            config: self.config.key(),
+            authority: self.user.key(),
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority(&mut self) -> Result<()> {
+        // Renouncing clears the pending authority, so this can't revive an immutable pool
+        match self.config.pending_authority {
+            Some(pending) => require_keys_eq!(pending, self.user.key(), AmmError::InvalidAuthority),
+            None => return err!(AmmError::NoPendingAuthority)
+        }
+        self.config.authority = Some(self.user.key());
+        self.config.pending_authority = None;
+
+        emit!(AuthorityAccepted {
+            config: self.config.key(),
+            authority: self.user.key(),
+        });
+        Ok(())
+    }
+
+    pub fn renounce_authority(&mut self) -> Result<()> {
+        assert_has_authority!(self);
+        // Nobody could lift the pause afterwards, LPs would never get out
+        require!(self.config.paused & PAUSE_WITHDRAW == 0, AmmError::WithdrawPaused);
+        self.config.authority = None;
+        self.config.pending_authority = None;
+        // Pauses could no longer be lifted either
+        self.config.pauser = None;
+
+        emit!(AuthorityRenounced {
+            config: self.config.key(),
+            authority: self.user.key(),
+        });
+        Ok(())
+    }
+}
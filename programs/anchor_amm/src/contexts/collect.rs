@@ -0,0 +1,123 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    token_interface::{TransferChecked, TokenAccount}
+};
+use crate::assert_has_authority;
+use crate::errors::AmmError;
+use crate::state::Factory;
+use crate::events::{ProtocolFeesCollected, ReservesSkimmed};
+use super::pool::*;
+
+#[derive(Accounts)]
+pub struct Collect<'info> {
+    #[account(mut)]
+    user: Signer<'info>,
+
+    pool: Pool<'info>,
+
+    #[account(
+        seeds = [b"factory"],
+        bump = factory.bump,
+    )]
+    factory: Box<Account<'info, Factory>>,
+
+    // Protocol fees and skimmed tokens go to the factory's recipient
+    #[account(
+        mut,
+        associated_token::mint = pool.mint_x,
+        associated_token::authority = factory.protocol_fee_recipient,
+        associated_token::token_program = pool.token_program,
+    )]
+    treasury_x: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool.mint_y,
+        associated_token::authority = factory.protocol_fee_recipient,
+        associated_token::token_program = pool.token_program,
+    )]
+    treasury_y: Box<InterfaceAccount<'info, TokenAccount>>,
+}
+
+impl <'info> Collect<'info> {
+    // Anyone can trigger it, the fees can only go to the recipient
+    pub fn collect_protocol_fees(&mut self, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let (x, y) = (self.pool.config.protocol_fees_x, self.pool.config.protocol_fees_y);
+        require!(x > 0 || y > 0, AmmError::NoProtocolFees);
+
+        // Clear the accrued amounts before moving tokens out of the vaults
+        self.pool.config.protocol_fees_x = 0;
+        self.pool.config.protocol_fees_y = 0;
+
+        if x > 0 {
+            self.collect_tokens(true, x, remaining_accounts)?;
+        }
+        if y > 0 {
+            self.collect_tokens(false, y, remaining_accounts)?;
+        }
+
+        emit!(ProtocolFeesCollected {
+            config: self.pool.config.key(),
+            amount_x: x,
+            amount_y: y,
+        });
+        Ok(())
+    }
+
+    // Sends what the vaults hold beyond the tracked reserves and protocol fees to the treasuries
+    pub fn skim(&mut self, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_has_authority!(self.pool.config, self.user);
+        require!(self.pool.config.flash_loan.is_none(), AmmError::FlashLoanActive);
+
+        let (vault_x, vault_y) = self.pool.vault_reserves()?;
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
+        let x = vault_x.checked_sub(reserve_x).ok_or(AmmError::Underflow)?;
+        let y = vault_y.checked_sub(reserve_y).ok_or(AmmError::Underflow)?;
+        require!(x > 0 || y > 0, AmmError::NothingToSkim);
+
+        if x > 0 {
+            self.collect_tokens(true, x, remaining_accounts)?;
+        }
+        if y > 0 {
+            self.collect_tokens(false, y, remaining_accounts)?;
+        }
+
+        emit!(ReservesSkimmed {
+            config: self.pool.config.key(),
+            amount_x: x,
+            amount_y: y,
+        });
+        Ok(())
+    }
+
+    pub fn collect_tokens(&mut self, is_x: bool, amount: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+
+        let binding_mint_x = self.pool.mint_x.to_account_info().key();
+        let binding_mint_y = self.pool.mint_y.to_account_info().key();
+        let binding_seed = self.pool.config.seed.to_le_bytes();
+        let seeds = &[
+            &b"amm"[..],
+            binding_mint_x.as_ref(),
+            binding_mint_y.as_ref(),
+            binding_seed.as_ref(),
+            &[self.pool.config.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let (mint, decimals, vault, treasury) = match is_x {
+            true => (self.pool.mint_x.to_account_info(), self.pool.mint_x.decimals, self.pool.vault_x.to_account_info(), self.treasury_x.to_account_info()),
+            false => (self.pool.mint_y.to_account_info(), self.pool.mint_y.decimals, self.pool.vault_y.to_account_info(), self.treasury_y.to_account_info()),
+        };
+
+        let accounts = TransferChecked {
+            from: vault,
+            to: treasury,
+            authority: self.pool.config.to_account_info(),
+            mint
+        };
+
+        let ctx = CpiContext::new_with_signer(self.pool.token_program.to_account_info(), accounts, signer_seeds)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)
+    }
+}
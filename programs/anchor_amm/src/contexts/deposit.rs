@@ -1,39 +1,45 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-    associated_token::AssociatedToken, 
-    token::{TransferChecked, transfer_checked, MintTo, mint_to}, 
-    token_interface::{ Mint, TokenAccount, TokenInterface}
+    associated_token::AssociatedToken,
+    token_interface::{TransferChecked, MintTo, mint_to, Mint, TokenAccount, TokenInterface}
 };
-use crate::{assert_not_locked, state::Config};
+use crate::{assert_not_paused, state::{MINIMUM_LIQUIDITY, PAUSE_DEPOSIT, PAUSE_SWAP}};
 use crate::errors::AmmError;
+use super::pool::*;
+use crate::events::LiquidityAdded;
 
-use constant_product_curve::ConstantProduct;
 use crate::assert_non_zero;
 
 #[derive(Accounts)]
-#[instruction(seed: u64)]
 pub struct Deposit<'info> {
     #[account(mut)]
     user: Signer<'info>,
 
-    mint_x: InterfaceAccount<'info, Mint>,
-    mint_y: InterfaceAccount<'info, Mint>,
+    pool: Pool<'info>,
+
+    // init_if_needed can't reach into the Pool, these repeat its LP mint and token program
+    #[account(address = pool.mint_lp.key())]
+    mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool.token_program.key())]
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
 
     #[account(
         mut,
-        associated_token::mint = mint_x,
+        associated_token::mint = pool.mint_x,
         associated_token::authority = user,
-        associated_token::token_program = token_program,
+        associated_token::token_program = pool.token_program,
     )]
-    user_ata_x: InterfaceAccount<'info, TokenAccount>,
+    user_ata_x: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_y,
+        associated_token::mint = pool.mint_y,
         associated_token::authority = user,
-        associated_token::token_program = token_program,
+        associated_token::token_program = pool.token_program,
     )]
-    user_ata_y: InterfaceAccount<'info, TokenAccount>,
+    user_ata_y: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
@@ -42,69 +48,139 @@ pub struct Deposit<'info> {
         associated_token::authority = user,
         associated_token::token_program = token_program,
     )]
-    user_ata_lp: InterfaceAccount<'info, TokenAccount>,
-
-    #[account(
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-        associated_token::token_program = token_program,
-    )]
-    vault_x: InterfaceAccount<'info, TokenAccount>,
-
-    #[account(
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-        associated_token::token_program = token_program,
-    )]
-    vault_y: InterfaceAccount<'info, TokenAccount>,
+    user_ata_lp: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [b"mint", config.key().as_ref()],
-        bump = config.lp_bump,
-        // mint::authority = config,
-        // mint::decimals = 6,
-        // mint::freeze_authority = config,
-        // mint::token_program = token_program,
-    )]
-    mint_lp: InterfaceAccount<'info, Mint>,
-
-    #[account(
-        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), seed.to_le_bytes().as_ref()],
-        bump = config.bump,
+        seeds = [b"locked", pool.config.key().as_ref()],
+        bump,
     )]
-    config: Account<'info, Config>,
-
-    token_program: Interface<'info, TokenInterface>,
-    associated_token_program: Program<'info, AssociatedToken>,
-    system_program: Program<'info, System>,
+    locked_lp: Box<InterfaceAccount<'info, TokenAccount>>,
 }
 
 impl <'info> Deposit<'info> {
-    pub fn deposit(&mut self, amount:u64, max_x: u64, max_y: u64) -> Result<()> {
-        require!(!self.config.locked, AmmError::PoolLocked);
+    pub fn deposit(&mut self, amount:u64, max_x: u64, max_y: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_DEPOSIT, AmmError::DepositPaused);
         assert_non_zero!([amount, max_x, max_y]);
-        assert_not_locked!(self);
 
-        let (x, y) = match self.mint_lp.supply == 0 && self.vault_x.amount == 0 && self.vault_y.amount == 0 {
-            true => (max_x, max_y),
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
+
+        let clock = Clock::get()?;
+        let curve = self.pool.curve(clock.unix_timestamp)?;
+        self.pool.update_oracle(&curve, (reserve_x, reserve_y), &clock)?;
+
+        // (x, y) are what the vaults must receive, max_x and max_y bound what the user sends.
+        // The first deposit sets the price and its LP follows from the invariant, `amount` is
+        // then the minimum the user accepts.
+        let first_deposit = self.pool.mint_lp.supply == 0;
+        let (x, y, amount) = match first_deposit {
+            true => {
+                let (x, y) = (self.pool.net_amount(true, max_x)?, self.pool.net_amount(false, max_y)?);
+                let liquidity = curve.initial_liquidity(x, y)?;
+                require!(liquidity > MINIMUM_LIQUIDITY, AmmError::InsufficientInitialLiquidity);
+                let lp_out = liquidity - MINIMUM_LIQUIDITY;
+                require!(lp_out >= amount, AmmError::SlippageExceeded);
+                (x, y, lp_out)
+            },
             false => {
-                let amounts = ConstantProduct::xy_deposit_amounts_from_l(self.vault_x.amount, self.vault_y.amount, self.mint_lp.supply, amount, 6).map_err(AmmError::from)?;
-                (amounts.x, amounts.y)
-            }
+                let (x, y) = curve.deposit_amounts(reserve_x, reserve_y, self.pool.mint_lp.supply, amount)?;
+                (x, y, amount)
+            },
+        };
+        let (sent_x, sent_y) = (self.pool.gross_amount(true, x)?, self.pool.gross_amount(false, y)?);
+
+        require!(sent_x <= max_x && sent_y <= max_y, AmmError::SlippageExceeded);
+
+        self.deposit_tokens(true, sent_x, remaining_accounts)?;
+        self.deposit_tokens(false, sent_y, remaining_accounts)?;
+        if first_deposit {
+            let locked_lp = self.locked_lp.to_account_info();
+            self.mint_lp_token(locked_lp, MINIMUM_LIQUIDITY)?;
+        }
+        let user_ata_lp = self.user_ata_lp.to_account_info();
+        self.mint_lp_token(user_ata_lp, amount)?;
+
+        let reserve_x = reserve_x.checked_add(x).ok_or(AmmError::Overflow)?;
+        let reserve_y = reserve_y.checked_add(y).ok_or(AmmError::Overflow)?;
+        self.pool.set_reserves((reserve_x, reserve_y));
+
+        emit!(LiquidityAdded {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            amount_x: x,
+            amount_y: y,
+            lp_minted: amount,
+            reserve_x,
+            reserve_y,
+            lp_supply: match first_deposit {
+                true => amount + MINIMUM_LIQUIDITY,
+                false => self.pool.mint_lp.supply.checked_add(amount).ok_or(AmmError::Overflow)?,
+            },
+        });
+        Ok(())
+    }
+
+    pub fn deposit_single(&mut self, mint_in: Pubkey, amount_in: u64, min_lp_out: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_DEPOSIT, AmmError::DepositPaused);
+        // Part of the input goes through the curve like a regular swap
+        assert_not_paused!(self.pool, PAUSE_SWAP, AmmError::SwapPaused);
+        assert_non_zero!([amount_in, min_lp_out, self.pool.mint_lp.supply]);
+
+        let is_x = match mint_in {
+            m if m == self.pool.mint_x.key() => true,
+            m if m == self.pool.mint_y.key() => false,
+            _ => return Err(AmmError::InvalidInputMint.into())
         };
-        
-        require!(x <= max_x && y <= max_y, AmmError::SlippageExceeded);
 
-        self.deposit_tokens(true, x)?;
-        self.deposit_tokens(false, y)?;
-        self.mint_lp_token(amount)
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
+
+        let clock = Clock::get()?;
+        self.pool.apply_pending_fee(clock.unix_timestamp);
+        let curve = self.pool.curve(clock.unix_timestamp)?;
+        self.pool.update_oracle(&curve, (reserve_x, reserve_y), &clock)?;
+
+        // LP is minted for what reaches the vault
+        let received = self.pool.net_amount(is_x, amount_in)?;
+        let quote = curve.deposit_single(is_x, reserve_x, reserve_y, self.pool.mint_lp.supply, received)?;
+        require!(quote.lp_amount >= min_lp_out, AmmError::SlippageExceeded);
+
+        // The implied swap pays the pool fee, with the usual protocol share
+        let protocol_fee = self.pool.config.accrue_protocol_fee(is_x, quote.fee)?;
+
+        self.deposit_tokens(is_x, amount_in, remaining_accounts)?;
+        let user_ata_lp = self.user_ata_lp.to_account_info();
+        self.mint_lp_token(user_ata_lp, quote.lp_amount)?;
+
+        let prices_before = curve.spot_prices(reserve_x, reserve_y)?;
+        let net_in = received.checked_sub(protocol_fee).ok_or(AmmError::Underflow)?;
+        let (amount_x, amount_y, reserve_x, reserve_y) = match is_x {
+            true => (received, 0, reserve_x.checked_add(net_in).ok_or(AmmError::Overflow)?, reserve_y),
+            false => (0, received, reserve_x, reserve_y.checked_add(net_in).ok_or(AmmError::Overflow)?),
+        };
+        self.pool.set_reserves((reserve_x, reserve_y));
+
+        // Feeds the dynamic fee of the next swaps
+        if let (Some((before, _)), Some((after, _))) = (prices_before, curve.spot_prices(reserve_x, reserve_y)?) {
+            self.pool.config.record_price_move(before, after, clock.unix_timestamp);
+        }
+
+        emit!(LiquidityAdded {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            amount_x,
+            amount_y,
+            lp_minted: quote.lp_amount,
+            reserve_x,
+            reserve_y,
+            lp_supply: self.pool.mint_lp.supply.checked_add(quote.lp_amount).ok_or(AmmError::Overflow)?,
+        });
+        Ok(())
     }
 
-    pub fn deposit_tokens(&mut self, is_x: bool, amount: u64) -> Result<()> {
+    pub fn deposit_tokens(&mut self, is_x: bool, amount: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
         let (mint, decimals, vault, ata) = match is_x {
-            true => (self.mint_x.to_account_info(), self.mint_x.decimals, self.vault_x.to_account_info(), self.user_ata_x.to_account_info()),
-            false => (self.mint_y.to_account_info(), self.mint_y.decimals, self.vault_y.to_account_info(), self.user_ata_y.to_account_info()),
+            true => (self.pool.mint_x.to_account_info(), self.pool.mint_x.decimals, self.pool.vault_x.to_account_info(), self.user_ata_x.to_account_info()),
+            false => (self.pool.mint_y.to_account_info(), self.pool.mint_y.decimals, self.pool.vault_y.to_account_info(), self.user_ata_y.to_account_info()),
         };
 
         let accounts = TransferChecked {
@@ -114,34 +190,34 @@ impl <'info> Deposit<'info> {
             mint: mint
         };
 
-        let ctx = CpiContext::new(self.token_program.to_account_info(), accounts);
-
-        transfer_checked(ctx, amount, decimals)?;
+        let ctx = CpiContext::new(self.pool.token_program.to_account_info(), accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)?;
 
         Ok(())
     }
 
-    pub fn mint_lp_token(&mut self, amount: u64) -> Result<()> {
+    pub fn mint_lp_token(&mut self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
         let accounts = MintTo {
-            mint: self.mint_lp.to_account_info(),
-            authority: self.config.to_account_info(),
-            to: self.user_ata_lp.to_account_info(),
+            mint: self.pool.mint_lp.to_account_info(),
+            authority: self.pool.config.to_account_info(),
+            to,
         };
 
-        let binding_mint_x = self.mint_x.to_account_info().key();
-        let binding_mint_y = self.mint_y.to_account_info().key();
-        let binding_seed = self.config.seed.to_le_bytes();
+        let binding_mint_x = self.pool.mint_x.to_account_info().key();
+        let binding_mint_y = self.pool.mint_y.to_account_info().key();
+        let binding_seed = self.pool.config.seed.to_le_bytes();
         let seeds = &[
             &b"amm"[..],
             &binding_mint_x.as_ref(),
             &binding_mint_y.as_ref(),
             &binding_seed.as_ref(),
-            &[self.config.bump],
+            &[self.pool.config.bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
         let ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
+            self.pool.token_program.to_account_info(),
             accounts,
             signer_seeds,
         );
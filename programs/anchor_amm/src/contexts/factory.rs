@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+
+use crate::program::AnchorAmm;
+use crate::state::{Factory, MAX_FEE_TIERS};
+use crate::errors::AmmError;
+use crate::events::FactoryUpdated;
+
+#[derive(Accounts)]
+pub struct CreateFactory<'info> {
+    #[account(mut)]
+    admin: Signer<'info>,
+
+    // Only whoever can upgrade the program sets it up
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    program: Program<'info, AnchorAmm>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ AmmError::InvalidAuthority)]
+    program_data: Account<'info, ProgramData>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Factory::INIT_SPACE,
+        seeds = [b"factory"],
+        bump
+    )]
+    factory: Account<'info, Factory>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> CreateFactory<'info> {
+    pub fn create_factory(&mut self, default_fee: u16, fee_tiers: Vec<u16>, protocol_fee_recipient: Pubkey, bumps: &CreateFactoryBumps) -> Result<()> {
+        self.factory.set_inner(Factory {
+            admin: self.admin.key(),
+            pool_count: 0,
+            default_fee,
+            fee_tiers,
+            protocol_fee_recipient,
+            bump: bumps.factory,
+        });
+        validate_fees(&self.factory)?;
+
+        emit_factory_updated(&self.factory);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct UpdateFactory<'info> {
+    admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin @ AmmError::InvalidAuthority,
+        seeds = [b"factory"],
+        bump = factory.bump,
+    )]
+    factory: Account<'info, Factory>,
+}
+
+impl<'info> UpdateFactory<'info> {
+    pub fn set_fee_tiers(&mut self, fee_tiers: Vec<u16>) -> Result<()> {
+        self.factory.fee_tiers = fee_tiers;
+        validate_fees(&self.factory)?;
+
+        emit_factory_updated(&self.factory);
+        Ok(())
+    }
+
+    pub fn set_default_fee(&mut self, default_fee: u16) -> Result<()> {
+        self.factory.default_fee = default_fee;
+        validate_fees(&self.factory)?;
+
+        emit_factory_updated(&self.factory);
+        Ok(())
+    }
+
+    pub fn set_protocol_fee_recipient(&mut self, protocol_fee_recipient: Pubkey) -> Result<()> {
+        self.factory.protocol_fee_recipient = protocol_fee_recipient;
+
+        emit_factory_updated(&self.factory);
+        Ok(())
+    }
+
+    pub fn set_factory_admin(&mut self, admin: Pubkey) -> Result<()> {
+        self.factory.admin = admin;
+
+        emit_factory_updated(&self.factory);
+        Ok(())
+    }
+}
+
+fn validate_fees(factory: &Factory) -> Result<()> {
+    require!(factory.fee_tiers.len() <= MAX_FEE_TIERS, AmmError::TooManyFeeTiers);
+    require!(factory.default_fee < 10000, AmmError::InvalidFeeSet);
+    require!(factory.fee_tiers.iter().all(|fee| *fee < 10000), AmmError::InvalidFeeSet);
+    Ok(())
+}
+
+fn emit_factory_updated(factory: &Account<Factory>) {
+    emit!(FactoryUpdated {
+        factory: factory.key(),
+        admin: factory.admin,
+        default_fee: factory.default_fee,
+        fee_tiers: factory.fee_tiers.clone(),
+        protocol_fee_recipient: factory.protocol_fee_recipient,
+    });
+}
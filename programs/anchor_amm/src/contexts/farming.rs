@@ -0,0 +1,559 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::state::{Config, Farm, Reward, Stake, MAX_REWARDS};
+use crate::errors::AmmError;
+use crate::events::{
+    FarmCreated, LpStaked, LpUnstaked, RewardAdded, RewardFunded, RewardEmissionsUpdated, RewardWithdrawn, RewardsClaimed,
+};
+use crate::{assert_has_authority, assert_non_zero};
+use super::pool::*;
+
+#[derive(Accounts)]
+pub struct CreateFarm<'info> {
+    #[account(mut)]
+    user: Signer<'info>,
+
+    #[account(
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    #[account(
+        seeds = [b"mint", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    mint_lp: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Farm::INIT_SPACE,
+        seeds = [b"farm", config.key().as_ref()],
+        bump
+    )]
+    farm: Box<Account<'info, Farm>>,
+
+    // Holds the staked LP, anyone can create it ahead of the farm
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_lp,
+        associated_token::authority = farm,
+        associated_token::token_program = token_program,
+    )]
+    farm_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> CreateFarm<'info> {
+    pub fn create_farm(&mut self, bumps: &CreateFarmBumps) -> Result<()> {
+        assert_has_authority!(self);
+
+        self.farm.set_inner(Farm {
+            config: self.config.key(),
+            total_staked: 0,
+            last_update_ts: Clock::get()?.unix_timestamp,
+            rewards: Vec::new(),
+            bump: bumps.farm,
+        });
+
+        emit!(FarmCreated {
+            config: self.config.key(),
+            farm: self.farm.key(),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct AddReward<'info> {
+    #[account(mut)]
+    user: Signer<'info>,
+
+    #[account(
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"farm", config.key().as_ref()],
+        bump = farm.bump,
+    )]
+    farm: Box<Account<'info, Farm>>,
+
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    // Anyone can create the associated account ahead of the reward
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = reward_mint,
+        associated_token::authority = farm,
+        associated_token::token_program = reward_token_program,
+    )]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    reward_token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> AddReward<'info> {
+    // Rewards can't be removed, set their emissions to zero instead
+    pub fn add_reward(&mut self, emissions_per_second: u64) -> Result<()> {
+        assert_has_authority!(self);
+        require!(self.farm.rewards.len() < MAX_REWARDS, AmmError::TooManyRewards);
+        // Rewards of the same mint would share a vault
+        require!(
+            self.farm.rewards.iter().all(|reward| reward.mint != self.reward_mint.key()),
+            AmmError::DuplicateReward
+        );
+
+        // Settles the existing rewards, the new one accrues from now on
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        self.farm.rewards.push(Reward {
+            mint: self.reward_mint.key(),
+            emissions_per_second,
+            remaining: 0,
+            owed: 0,
+            acc_reward_per_share: 0,
+        });
+
+        emit!(RewardAdded {
+            farm: self.farm.key(),
+            mint: self.reward_mint.key(),
+            emissions_per_second,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct FundReward<'info> {
+    user: Signer<'info>,
+
+    #[account(
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"farm", config.key().as_ref()],
+        bump = farm.bump,
+    )]
+    farm: Box<Account<'info, Farm>>,
+
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = farm,
+        associated_token::token_program = reward_token_program,
+    )]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = user,
+        associated_token::token_program = reward_token_program,
+    )]
+    user_reward_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    reward_token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FundReward<'info> {
+    pub fn fund_reward(&mut self, index: u8, amount: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_has_authority!(self);
+        assert_non_zero!([amount]);
+        let index = reward_index(&self.farm, index, self.reward_mint.key())?;
+
+        // Counts what reaches the vault after any transfer fee
+        let before = self.reward_vault.amount;
+        let accounts = TransferChecked {
+            from: self.user_reward_ata.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.reward_vault.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+        let ctx = CpiContext::new(self.reward_token_program.to_account_info(), accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, self.reward_mint.decimals)?;
+        self.reward_vault.reload()?;
+        let received = self.reward_vault.amount.checked_sub(before).ok_or(AmmError::Underflow)?;
+
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        let reward = &mut self.farm.rewards[index];
+        reward.remaining = reward.remaining.checked_add(received).ok_or(AmmError::Overflow)?;
+        let remaining = reward.remaining;
+
+        emit!(RewardFunded {
+            farm: self.farm.key(),
+            mint: self.reward_mint.key(),
+            amount: received,
+            remaining,
+        });
+        Ok(())
+    }
+
+    // Takes back what no staker is owed, the unemitted funds first and rounding dust after
+    pub fn withdraw_reward(&mut self, index: u8, amount: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_has_authority!(self);
+        assert_non_zero!([amount]);
+        let index = reward_index(&self.farm, index, self.reward_mint.key())?;
+
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        let reward = &mut self.farm.rewards[index];
+        let unallocated = self.reward_vault.amount.checked_sub(reward.owed).ok_or(AmmError::Underflow)?;
+        require!(amount <= unallocated, AmmError::InsufficientRewards);
+        reward.remaining = reward.remaining.saturating_sub(amount);
+        let remaining = reward.remaining;
+
+        let binding_config = self.farm.config;
+        let seeds = &[
+            &b"farm"[..],
+            binding_config.as_ref(),
+            &[self.farm.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let accounts = TransferChecked {
+            from: self.reward_vault.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.user_reward_ata.to_account_info(),
+            authority: self.farm.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(self.reward_token_program.to_account_info(), accounts, signer_seeds)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, self.reward_mint.decimals)?;
+
+        emit!(RewardWithdrawn {
+            farm: self.farm.key(),
+            mint: self.reward_mint.key(),
+            amount,
+            remaining,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct UpdateFarm<'info> {
+    user: Signer<'info>,
+
+    #[account(
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        has_one = config,
+        seeds = [b"farm", config.key().as_ref()],
+        bump = farm.bump,
+    )]
+    farm: Box<Account<'info, Farm>>,
+}
+
+impl<'info> UpdateFarm<'info> {
+    pub fn set_reward_emissions(&mut self, index: u8, emissions_per_second: u64) -> Result<()> {
+        assert_has_authority!(self);
+        require!((index as usize) < self.farm.rewards.len(), AmmError::InvalidRewardIndex);
+
+        // What accrued so far is emitted at the old rate
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        let reward = &mut self.farm.rewards[index as usize];
+        reward.emissions_per_second = emissions_per_second;
+        let mint = reward.mint;
+
+        emit!(RewardEmissionsUpdated {
+            farm: self.farm.key(),
+            mint,
+            emissions_per_second,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct StakeLp<'info> {
+    #[account(mut)]
+    owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"farm", farm.config.as_ref()],
+        bump = farm.bump,
+    )]
+    farm: Box<Account<'info, Farm>>,
+
+    #[account(
+        seeds = [b"mint", farm.config.as_ref()],
+        bump,
+    )]
+    mint_lp: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + Stake::INIT_SPACE,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    stake: Box<Account<'info, Stake>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = farm,
+        associated_token::token_program = token_program,
+    )]
+    farm_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    user_ata_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> StakeLp<'info> {
+    pub fn stake_lp(&mut self, amount: u64, bumps: &StakeLpBumps) -> Result<()> {
+        assert_non_zero!([amount]);
+        self.settle(bumps)?;
+
+        let accounts = TransferChecked {
+            from: self.user_ata_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.farm_lp.to_account_info(),
+            authority: self.owner.to_account_info(),
+        };
+        let ctx = CpiContext::new(self.token_program.to_account_info(), accounts);
+        transfer_checked(ctx, amount, self.mint_lp.decimals)?;
+
+        self.stake.amount = self.stake.amount.checked_add(amount).ok_or(AmmError::Overflow)?;
+        self.farm.total_staked = self.farm.total_staked.checked_add(amount).ok_or(AmmError::Overflow)?;
+        self.stake.reset_debts(&self.farm)?;
+
+        emit!(LpStaked {
+            farm: self.farm.key(),
+            owner: self.owner.key(),
+            amount,
+            staked: self.stake.amount,
+            total_staked: self.farm.total_staked,
+        });
+        Ok(())
+    }
+
+    // Brings the farm up to date and credits the stake, setting up a new one on first use
+    fn settle(&mut self, bumps: &StakeLpBumps) -> Result<()> {
+        if self.stake.owner == Pubkey::default() {
+            self.stake.set_inner(Stake {
+                farm: self.farm.key(),
+                owner: self.owner.key(),
+                amount: 0,
+                reward_debts: [0; MAX_REWARDS],
+                pending: [0; MAX_REWARDS],
+                bump: bumps.stake,
+            });
+        }
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        self.stake.settle(&self.farm)
+    }
+}
+
+#[derive(Accounts)]
+pub struct UnstakeLp<'info> {
+    owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"farm", farm.config.as_ref()],
+        bump = farm.bump,
+    )]
+    farm: Box<Account<'info, Farm>>,
+
+    #[account(
+        seeds = [b"mint", farm.config.as_ref()],
+        bump,
+    )]
+    mint_lp: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        has_one = farm,
+        has_one = owner,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump = stake.bump,
+    )]
+    stake: Box<Account<'info, Stake>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = farm,
+        associated_token::token_program = token_program,
+    )]
+    farm_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    user_ata_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> UnstakeLp<'info> {
+    // Earned rewards stay pending on the stake until claimed
+    pub fn unstake_lp(&mut self, amount: u64) -> Result<()> {
+        assert_non_zero!([amount]);
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        self.stake.settle(&self.farm)?;
+        require!(amount <= self.stake.amount, AmmError::InsufficientStake);
+
+        let binding_config = self.farm.config;
+        let seeds = &[
+            &b"farm"[..],
+            binding_config.as_ref(),
+            &[self.farm.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let accounts = TransferChecked {
+            from: self.farm_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.user_ata_lp.to_account_info(),
+            authority: self.farm.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), accounts, signer_seeds);
+        transfer_checked(ctx, amount, self.mint_lp.decimals)?;
+
+        self.stake.amount -= amount;
+        self.farm.total_staked = self.farm.total_staked.checked_sub(amount).ok_or(AmmError::Underflow)?;
+        self.stake.reset_debts(&self.farm)?;
+
+        emit!(LpUnstaked {
+            farm: self.farm.key(),
+            owner: self.owner.key(),
+            amount,
+            staked: self.stake.amount,
+            total_staked: self.farm.total_staked,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"farm", farm.config.as_ref()],
+        bump = farm.bump,
+    )]
+    farm: Box<Account<'info, Farm>>,
+
+    #[account(
+        mut,
+        has_one = farm,
+        has_one = owner,
+        seeds = [b"stake", farm.key().as_ref(), owner.key().as_ref()],
+        bump = stake.bump,
+    )]
+    stake: Box<Account<'info, Stake>>,
+
+    reward_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = farm,
+        associated_token::token_program = reward_token_program,
+    )]
+    reward_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = reward_mint,
+        associated_token::authority = owner,
+        associated_token::token_program = reward_token_program,
+    )]
+    user_reward_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    reward_token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ClaimRewards<'info> {
+    // Pays out one reward mint, call once per reward
+    pub fn claim_rewards(&mut self, index: u8, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let index = reward_index(&self.farm, index, self.reward_mint.key())?;
+
+        self.farm.update(Clock::get()?.unix_timestamp)?;
+        self.stake.settle(&self.farm)?;
+        let amount = self.stake.pending[index];
+        require!(amount > 0, AmmError::NoRewards);
+        self.stake.pending[index] = 0;
+        let reward = &mut self.farm.rewards[index];
+        reward.owed = reward.owed.saturating_sub(amount);
+
+        let binding_config = self.farm.config;
+        let seeds = &[
+            &b"farm"[..],
+            binding_config.as_ref(),
+            &[self.farm.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let accounts = TransferChecked {
+            from: self.reward_vault.to_account_info(),
+            mint: self.reward_mint.to_account_info(),
+            to: self.user_reward_ata.to_account_info(),
+            authority: self.farm.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(self.reward_token_program.to_account_info(), accounts, signer_seeds)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, self.reward_mint.decimals)?;
+
+        emit!(RewardsClaimed {
+            farm: self.farm.key(),
+            owner: self.owner.key(),
+            mint: self.reward_mint.key(),
+            amount,
+        });
+        Ok(())
+    }
+}
+
+// Checks `reward_mint` is the farm's reward number `index`
+fn reward_index(farm: &Farm, index: u8, reward_mint: Pubkey) -> Result<usize> {
+    let reward = farm.rewards.get(index as usize).ok_or(AmmError::InvalidRewardIndex)?;
+    require_keys_eq!(reward.mint, reward_mint, AmmError::InvalidRewardIndex);
+    Ok(index as usize)
+}
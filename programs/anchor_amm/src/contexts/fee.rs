@@ -0,0 +1,70 @@
+use anchor_lang::prelude::*;
+
+use crate::{assert_has_authority, state::{Config, DynamicFee, Factory, MIN_FEE_DELAY}};
+use crate::errors::AmmError;
+use crate::events::{DynamicFeeScheduled, FeeUpdateScheduled};
+
+#[derive(Accounts)]
+pub struct SetFee<'info> {
+    user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Account<'info, Config>,
+
+    #[account(
+        seeds = [b"factory"],
+        bump = factory.bump,
+    )]
+    factory: Account<'info, Factory>,
+}
+
+impl<'info> SetFee<'info> {
+    // Replaces any change already scheduled
+    pub fn set_fee(&mut self, fee_tier: u8, effective_at: i64) -> Result<()> {
+        assert_has_authority!(self);
+
+        let fee = *self.factory.fee_tiers.get(fee_tier as usize).ok_or(AmmError::InvalidFeeTier)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(effective_at >= now.saturating_add(MIN_FEE_DELAY), AmmError::FeeDelayTooShort);
+
+        self.config.pending_fee = Some(fee);
+        self.config.fee_effective_at = effective_at;
+
+        emit!(FeeUpdateScheduled {
+            config: self.config.key(),
+            user: self.user.key(),
+            fee,
+            effective_at,
+        });
+        Ok(())
+    }
+
+    // Schedule a switch between the static fee and volatility-based fees bounded by two fee tiers.
+    // Shares fee_effective_at with set_fee, LPs get the same notice for both.
+    pub fn set_dynamic_fee(&mut self, enabled: bool, min_fee_tier: u8, max_fee_tier: u8, effective_at: i64) -> Result<()> {
+        assert_has_authority!(self);
+
+        let min_fee = *self.factory.fee_tiers.get(min_fee_tier as usize).ok_or(AmmError::InvalidFeeTier)?;
+        let max_fee = *self.factory.fee_tiers.get(max_fee_tier as usize).ok_or(AmmError::InvalidFeeTier)?;
+        require!(min_fee <= max_fee, AmmError::InvalidDynamicFee);
+        let now = Clock::get()?.unix_timestamp;
+        require!(effective_at >= now.saturating_add(MIN_FEE_DELAY), AmmError::FeeDelayTooShort);
+
+        self.config.pending_dynamic_fee = Some(DynamicFee { enabled, min_fee, max_fee });
+        self.config.fee_effective_at = effective_at;
+
+        emit!(DynamicFeeScheduled {
+            config: self.config.key(),
+            user: self.user.key(),
+            enabled,
+            min_fee,
+            max_fee,
+            effective_at,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+use anchor_lang::Discriminator;
+use anchor_spl::token_interface::{TransferChecked, TokenAccount};
+
+use crate::{assert_not_paused, state::{FlashLoan, FLASH_LOAN_FEE, PAUSE_FLASH_LOAN}};
+use crate::errors::AmmError;
+use crate::events::{FlashBorrowed, FlashLoanRepaid};
+use crate::instruction::FlashRepay;
+use super::pool::*;
+
+// Position of the config in FlashRepay's accounts: user, mint_x, mint_y, config
+const REPAY_CONFIG_INDEX: usize = 3;
+
+#[derive(Accounts)]
+pub struct Flash<'info> {
+    #[account(mut)]
+    user: Signer<'info>,
+
+    pool: Pool<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool.mint_x,
+        associated_token::authority = user,
+        associated_token::token_program = pool.token_program,
+    )]
+    user_ata_x: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool.mint_y,
+        associated_token::authority = user,
+        associated_token::token_program = pool.token_program,
+    )]
+    user_ata_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: The instructions sysvar, checked by address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    instructions: UncheckedAccount<'info>,
+}
+
+impl<'info> Flash<'info> {
+    pub fn flash_borrow(&mut self, mint: Pubkey, amount: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_FLASH_LOAN, AmmError::FlashLoanPaused);
+        require!(amount > 0, AmmError::ZeroBalance);
+        let is_x = self.is_x(mint)?;
+        self.require_repay()?;
+
+        let vault_balance = match is_x {
+            true => self.pool.vault_x.amount,
+            false => self.pool.vault_y.amount,
+        };
+        require!(amount <= vault_balance, AmmError::InsufficientBalance);
+        let fee = (amount as u128 * FLASH_LOAN_FEE as u128).div_ceil(10_000) as u64;
+
+        self.pool.config.flash_loan = Some(FlashLoan { is_x, amount, fee, vault_balance });
+
+        let (mint, decimals, from, to) = self.accounts(is_x, false);
+        let accounts = TransferChecked { from, mint, to, authority: self.pool.config.to_account_info() };
+
+        let binding_mint_x = self.pool.mint_x.key();
+        let binding_mint_y = self.pool.mint_y.key();
+        let binding_seed = self.pool.config.seed.to_le_bytes();
+        let seeds = &[
+            &b"amm"[..],
+            binding_mint_x.as_ref(),
+            binding_mint_y.as_ref(),
+            binding_seed.as_ref(),
+            &[self.pool.config.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let ctx = CpiContext::new_with_signer(self.pool.token_program.to_account_info(), accounts, signer_seeds)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)?;
+
+        emit!(FlashBorrowed {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            mint: match is_x {
+                true => self.pool.mint_x.key(),
+                false => self.pool.mint_y.key(),
+            },
+            amount,
+            fee,
+        });
+        Ok(())
+    }
+
+    pub fn flash_repay(&mut self, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let loan = self.pool.config.flash_loan.ok_or(AmmError::NoFlashLoan)?;
+
+        // Principal and fee have to reach the vault after any transfer fee
+        let owed = loan.amount.checked_add(loan.fee).ok_or(AmmError::Overflow)?;
+        let amount = self.pool.gross_amount(loan.is_x, owed)?;
+
+        let (mint, decimals, from, to) = self.accounts(loan.is_x, true);
+        let accounts = TransferChecked { from, mint, to, authority: self.user.to_account_info() };
+        let ctx = CpiContext::new(self.pool.token_program.to_account_info(), accounts)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)?;
+
+        let vault = match loan.is_x {
+            true => &mut self.pool.vault_x,
+            false => &mut self.pool.vault_y,
+        };
+        vault.reload()?;
+        require!(
+            vault.amount >= loan.vault_balance.checked_add(loan.fee).ok_or(AmmError::Overflow)?,
+            AmmError::FlashLoanNotRepaid
+        );
+
+        // The LP share of the fee joins the reserves
+        let protocol_fee = self.pool.config.accrue_protocol_fee(loan.is_x, loan.fee)?;
+        let lp_fee = loan.fee.checked_sub(protocol_fee).ok_or(AmmError::Underflow)?;
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
+        let clock = Clock::get()?;
+        let curve = self.pool.curve(clock.unix_timestamp)?;
+        self.pool.update_oracle(&curve, (reserve_x, reserve_y), &clock)?;
+        self.pool.set_reserves(match loan.is_x {
+            true => (reserve_x.checked_add(lp_fee).ok_or(AmmError::Overflow)?, reserve_y),
+            false => (reserve_x, reserve_y.checked_add(lp_fee).ok_or(AmmError::Overflow)?),
+        });
+        self.pool.config.flash_loan = None;
+
+        emit!(FlashLoanRepaid {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            mint: match loan.is_x {
+                true => self.pool.mint_x.key(),
+                false => self.pool.mint_y.key(),
+            },
+            amount: loan.amount,
+            fee: loan.fee,
+            protocol_fee,
+        });
+        Ok(())
+    }
+
+    fn is_x(&self, mint: Pubkey) -> Result<bool> {
+        match mint {
+            m if m == self.pool.mint_x.key() => Ok(true),
+            m if m == self.pool.mint_y.key() => Ok(false),
+            _ => err!(AmmError::InvalidInputMint),
+        }
+    }
+
+    // Mint, decimals and the (from, to) token accounts of a transfer into or out of a vault
+    fn accounts(&self, is_x: bool, into_vault: bool) -> (AccountInfo<'info>, u8, AccountInfo<'info>, AccountInfo<'info>) {
+        let (mint, vault, ata) = match is_x {
+            true => (&self.pool.mint_x, self.pool.vault_x.to_account_info(), self.user_ata_x.to_account_info()),
+            false => (&self.pool.mint_y, self.pool.vault_y.to_account_info(), self.user_ata_y.to_account_info()),
+        };
+        let (from, to) = if into_vault { (ata, vault) } else { (vault, ata) };
+        (mint.to_account_info(), mint.decimals, from, to)
+    }
+
+    // A flash_repay for this pool has to come later in the same transaction
+    fn require_repay(&self) -> Result<()> {
+        let instructions = self.instructions.to_account_info();
+        let current = load_current_index_checked(&instructions)? as usize;
+        let config = self.pool.config.key();
+
+        let mut index = current + 1;
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if ix.program_id == crate::ID
+                && ix.data.get(..8) == Some(&FlashRepay::DISCRIMINATOR[..])
+                && ix.accounts.get(REPAY_CONFIG_INDEX).map(|meta| meta.pubkey) == Some(config)
+            {
+                return Ok(());
+            }
+            index += 1;
+        }
+        err!(AmmError::FlashRepayMissing)
+    }
+}
@@ -2,11 +2,20 @@ use anchor_lang::prelude::*;
 // use anchor_spl::{associated_token::AssociatedToken, token::{Mint, Token, TokenAccount}};
 use anchor_spl::{
     associated_token::AssociatedToken, 
+    token_2022::spl_token_2022::{
+        self,
+        extension::{permanent_delegate::PermanentDelegate, transfer_hook, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    },
     token_interface::{ Mint, TokenAccount, TokenInterface }
 };
 
-use crate::state::Config;
+use crate::curves::{stable_swap::{MAX_AMP, MAX_DECIMALS_GAP, MIN_AMP}, weighted::{MAX_WEIGHT, MIN_WEIGHT}};
+use crate::state::{
+    Config, CurveType, Factory, Observation, Oracle, PoolEntry, OBSERVATION_CAPACITY,
+    ALLOW_ALL, ALLOW_FREEZE_AUTHORITY, ALLOW_NON_TRANSFERABLE, ALLOW_PERMANENT_DELEGATE, ALLOW_TRANSFER_HOOK,
+};
 use crate::errors::AmmError;
+use crate::events::PoolInitialized;
 
 #[derive(Accounts)]
 #[instruction(seed: u64)]
@@ -30,6 +39,32 @@ pub struct Initialize<'info> {
     )]
     config: Account<'info, Config>,
 
+    #[account(
+        mut,
+        seeds = [b"factory"],
+        bump = factory.bump,
+    )]
+    factory: Box<Account<'info, Factory>>,
+
+    // Registers the pool under the next factory index
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + PoolEntry::INIT_SPACE,
+        seeds = [b"pool", factory.pool_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pool_entry: Box<Account<'info, PoolEntry>>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Oracle::INIT_SPACE,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump
+    )]
+    oracle: Box<Account<'info, Oracle>>,
+
     #[account(
         init_if_needed,
         payer = admin,
@@ -42,6 +77,22 @@ pub struct Initialize<'info> {
     )]
     mint_lp: InterfaceAccount<'info, Mint>,
 
+    /// CHECK: Owner of the locked LP, the program never signs for it
+    #[account(seeds = [b"dead"], bump)]
+    dead: UncheckedAccount<'info>,
+
+    // Receives MINIMUM_LIQUIDITY on the first deposit, locked for good
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"locked", config.key().as_ref()],
+        bump,
+        token::mint = mint_lp,
+        token::authority = dead,
+        token::token_program = token_program,
+    )]
+    locked_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
     #[account(
         init_if_needed,
         payer = admin,
@@ -67,18 +118,139 @@ pub struct Initialize<'info> {
 
 
 impl<'info> Initialize<'info> {
-    pub fn save_config(&mut self, seed: u64, fee: u16, bumps: &InitializeBumps) -> Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn save_config(&mut self, seed: u64, fee_tier: Option<u8>, protocol_fee: u16, curve_type: CurveType, amp: u64, weight_x: u16, allowed_extensions: u8, bumps: &InitializeBumps) -> Result<()> {
+        // One ordering per pair, so X/Y and Y/X pools can't split liquidity
+        require_keys_neq!(self.mint_x.key(), self.mint_y.key(), AmmError::IdenticalMints);
+        require!(self.mint_x.key() < self.mint_y.key(), AmmError::MintsNotSorted);
+
+        require!(allowed_extensions & !ALLOW_ALL == 0, AmmError::InvalidExtensionFlags);
+        check_mint_extensions(&self.mint_x, allowed_extensions)?;
+        check_mint_extensions(&self.mint_y, allowed_extensions)?;
+
+        // Pools can only use a fee tier offered by the factory or its default fee
+        let fee = match fee_tier {
+            Some(tier) => *self.factory.fee_tiers.get(tier as usize).ok_or(AmmError::InvalidFeeTier)?,
+            None => self.factory.default_fee,
+        };
+        require!( fee < 10000, AmmError::InvalidFeeSet);
+        require!( protocol_fee <= 10000, AmmError::InvalidProtocolFeeSet);
+
+        let clock = Clock::get()?;
+        // Parameters of other curve types are ignored
+        let (amp, weight_x) = match curve_type {
+            CurveType::ConstantProduct => (0, 5_000),
+            CurveType::StableSwap => {
+                require!((MIN_AMP..=MAX_AMP).contains(&amp), AmmError::InvalidAmp);
+                require!(self.mint_x.decimals.abs_diff(self.mint_y.decimals) <= MAX_DECIMALS_GAP, AmmError::InvalidPrecision);
+                (amp, 5_000)
+            },
+            CurveType::Weighted => {
+                require!((MIN_WEIGHT..=MAX_WEIGHT).contains(&weight_x), AmmError::InvalidWeight);
+                (0, weight_x)
+            },
+        };
+        let index = self.factory.pool_count;
+        self.factory.pool_count = index.checked_add(1).ok_or(AmmError::Overflow)?;
+        self.pool_entry.set_inner(PoolEntry {
+            config: self.config.key(),
+            bump: bumps.pool_entry,
+        });
+
         self.config.set_inner(Config {
-            authority: self.admin.key(),
+            index,
+            authority: Some(self.admin.key()),
+            pending_authority: None,
+            pauser: None,
             seed,
             fee,
-            locked : false,
+            pending_fee: None,
+            fee_effective_at: 0,
+            dynamic_fee: false,
+            min_fee: fee,
+            max_fee: fee,
+            pending_dynamic_fee: None,
+            volatility: 0,
+            volatility_updated_at: clock.unix_timestamp,
+            protocol_fee,
+            protocol_fees_x: 0,
+            protocol_fees_y: 0,
+            reserve_x: 0,
+            reserve_y: 0,
+            paused: 0,
+            flash_loan: None,
+            allowed_extensions,
+            price_x_cumulative: 0,
+            price_y_cumulative: 0,
+            last_update_slot: clock.slot,
+            last_update_timestamp: clock.unix_timestamp,
             mint_x: self.mint_x.key(),
             mint_y: self.mint_y.key(),
+            curve_type,
+            initial_amp: amp,
+            target_amp: amp,
+            ramp_start_ts: 0,
+            ramp_end_ts: 0,
+            weight_x,
             lp_bump: bumps.mint_lp,
             bump: bumps.config,
         });
-        require!( fee < 10000, AmmError::InvalidFeeSet);
+        // Seed the ring buffer so the first window can be measured from pool creation
+        let mut observations = [Observation::default(); OBSERVATION_CAPACITY];
+        observations[0].timestamp = clock.unix_timestamp;
+        self.oracle.set_inner(Oracle {
+            config: self.config.key(),
+            index: 0,
+            observations,
+            bump: bumps.oracle,
+        });
+
+        emit!(PoolInitialized {
+            config: self.config.key(),
+            index,
+            authority: self.admin.key(),
+            mint_x: self.mint_x.key(),
+            mint_y: self.mint_y.key(),
+            mint_lp: self.mint_lp.key(),
+            seed,
+            fee,
+            protocol_fee,
+            curve_type,
+            amp,
+            weight_x,
+            allowed_extensions,
+        });
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Rejects mint features that let a third party freeze, drain or block the vaults unless the
+// creator opted in. A frozen DefaultAccountState needs a freeze authority, so it is covered too.
+fn check_mint_extensions(mint: &InterfaceAccount<Mint>, allowed: u8) -> Result<()> {
+    let allows = |flag: u8| allowed & flag != 0;
+    require!(mint.freeze_authority.is_none() || allows(ALLOW_FREEZE_AUTHORITY), AmmError::MintFreezeAuthority);
+
+    let info = mint.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    for extension in mint.get_extension_types()? {
+        match extension {
+            ExtensionType::PermanentDelegate => {
+                let delegate: Option<Pubkey> = mint.get_extension::<PermanentDelegate>()?.delegate.into();
+                require!(delegate.is_none() || allows(ALLOW_PERMANENT_DELEGATE), AmmError::MintPermanentDelegate);
+            },
+            ExtensionType::NonTransferable => {
+                require!(allows(ALLOW_NON_TRANSFERABLE), AmmError::MintNonTransferable);
+            },
+            ExtensionType::TransferHook => {
+                let hook_program = transfer_hook::get_program_id(&mint);
+                require!(hook_program.is_none() || allows(ALLOW_TRANSFER_HOOK), AmmError::MintTransferHook);
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
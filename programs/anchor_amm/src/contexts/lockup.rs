@@ -0,0 +1,207 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::state::{Config, Position};
+use crate::errors::AmmError;
+use crate::events::{LiquidityLocked, LiquidityUnlocked, LockExtended};
+use crate::assert_non_zero;
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct LockLiquidity<'info> {
+    #[account(mut)]
+    owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"amm".as_ref(), config.mint_x.as_ref(), config.mint_y.as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    #[account(
+        seeds = [b"mint", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    mint_lp: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Position::INIT_SPACE,
+        seeds = [b"position", config.key().as_ref(), owner.key().as_ref(), id.to_le_bytes().as_ref()],
+        bump
+    )]
+    position: Box<Account<'info, Position>>,
+
+    // Anyone can create the associated account ahead of the lock
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_lp,
+        associated_token::authority = position,
+        associated_token::token_program = token_program,
+    )]
+    position_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    user_ata_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
+}
+
+impl<'info> LockLiquidity<'info> {
+    pub fn lock_liquidity(&mut self, id: u64, amount: u64, unlock_at: i64, bumps: &LockLiquidityBumps) -> Result<()> {
+        assert_non_zero!([amount]);
+        require!(unlock_at > Clock::get()?.unix_timestamp, AmmError::InvalidUnlockTime);
+
+        let accounts = TransferChecked {
+            from: self.user_ata_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.position_lp.to_account_info(),
+            authority: self.owner.to_account_info(),
+        };
+        let ctx = CpiContext::new(self.token_program.to_account_info(), accounts);
+        transfer_checked(ctx, amount, self.mint_lp.decimals)?;
+
+        self.position.set_inner(Position {
+            config: self.config.key(),
+            owner: self.owner.key(),
+            id,
+            amount,
+            unlock_at,
+            bump: bumps.position,
+        });
+
+        emit!(LiquidityLocked {
+            config: self.config.key(),
+            position: self.position.key(),
+            owner: self.owner.key(),
+            amount,
+            unlock_at,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ExtendLock<'info> {
+    owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"position", position.config.as_ref(), owner.key().as_ref(), position.id.to_le_bytes().as_ref()],
+        bump = position.bump,
+    )]
+    position: Account<'info, Position>,
+}
+
+impl<'info> ExtendLock<'info> {
+    // Locks can only be pushed further out
+    pub fn extend_lock(&mut self, unlock_at: i64) -> Result<()> {
+        require!(unlock_at > self.position.unlock_at, AmmError::InvalidUnlockTime);
+        self.position.unlock_at = unlock_at;
+
+        emit!(LockExtended {
+            config: self.position.config,
+            position: self.position.key(),
+            owner: self.owner.key(),
+            unlock_at,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct UnlockLiquidity<'info> {
+    #[account(mut)]
+    owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"mint", position.config.as_ref()],
+        bump,
+    )]
+    mint_lp: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        seeds = [b"position", position.config.as_ref(), owner.key().as_ref(), position.id.to_le_bytes().as_ref()],
+        bump = position.bump,
+    )]
+    position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = position,
+        associated_token::token_program = token_program,
+    )]
+    position_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    user_ata_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> UnlockLiquidity<'info> {
+    // Returns the LP once unlock_at has passed and closes the position
+    pub fn unlock_liquidity(&mut self) -> Result<()> {
+        require!(Clock::get()?.unix_timestamp >= self.position.unlock_at, AmmError::LiquidityLocked);
+
+        let binding_config = self.position.config;
+        let binding_owner = self.owner.key();
+        let binding_id = self.position.id.to_le_bytes();
+        let seeds = &[
+            &b"position"[..],
+            binding_config.as_ref(),
+            binding_owner.as_ref(),
+            binding_id.as_ref(),
+            &[self.position.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let accounts = TransferChecked {
+            from: self.position_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.user_ata_lp.to_account_info(),
+            authority: self.position.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), accounts, signer_seeds);
+        let amount = self.position_lp.amount;
+        transfer_checked(ctx, amount, self.mint_lp.decimals)?;
+
+        let accounts = CloseAccount {
+            account: self.position_lp.to_account_info(),
+            destination: self.owner.to_account_info(),
+            authority: self.position.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), accounts, signer_seeds);
+        close_account(ctx)?;
+
+        emit!(LiquidityUnlocked {
+            config: self.position.config,
+            position: self.position.key(),
+            owner: self.owner.key(),
+            amount,
+        });
+        Ok(())
+    }
+}
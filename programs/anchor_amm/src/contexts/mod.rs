@@ -1,6 +1,9 @@
 pub mod initialize;
 pub use initialize::*;
 
+pub mod pool;
+pub use pool::*;
+
 pub mod deposit;
 pub use deposit::*;
 
@@ -11,4 +14,34 @@ pub mod swap;
 pub use swap::*;
 
 pub mod update;
-pub use update::*;
\ No newline at end of file
+pub use update::*;
+
+pub mod collect;
+pub use collect::*;
+
+pub mod authority;
+pub use authority::*;
+
+pub mod observe;
+pub use observe::*;
+
+pub mod register;
+pub use register::*;
+
+pub mod factory;
+pub use factory::*;
+
+pub mod fee;
+pub use fee::*;
+
+pub mod flash;
+pub use flash::*;
+
+pub mod reconcile;
+pub use reconcile::*;
+
+pub mod lockup;
+pub use lockup::*;
+
+pub mod farming;
+pub use farming::*;
\ No newline at end of file
@@ -0,0 +1,36 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use crate::curves::Curve;
+use crate::errors::AmmError;
+use crate::state::{Config, Oracle, Twap};
+
+// Read-only, so it doesn't share the mutable Pool accounts
+#[derive(Accounts)]
+pub struct Observe<'info> {
+    mint_x: Box<InterfaceAccount<'info, Mint>>,
+    mint_y: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    #[account(
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    oracle: Box<Account<'info, Oracle>>,
+}
+
+impl<'info> Observe<'info> {
+    pub fn observe(&self, window: u32) -> Result<Twap> {
+        let now = Clock::get()?.unix_timestamp;
+        let curve = Curve::new(&self.config, self.mint_x.decimals, self.mint_y.decimals, now)?;
+
+        let spot_prices = curve.spot_prices(self.config.reserve_x, self.config.reserve_y)?.ok_or(AmmError::ZeroBalance)?;
+        self.oracle.twap(&self.config, spot_prices, now, window)
+    }
+}
@@ -0,0 +1,164 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    onchain::invoke_transfer_checked,
+};
+use anchor_spl::token_interface::{ Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::curves::Curve;
+use crate::errors::AmmError;
+use crate::events::{DynamicFeeUpdated, FeeUpdated};
+use crate::state::{Config, Oracle};
+
+// Accounts every instruction touching a pool's reserves needs, validated in one place so the
+// constraints cannot drift between instructions. Nest it in a context as `pool: Pool<'info>`.
+#[derive(Accounts)]
+pub struct Pool<'info> {
+    pub mint_x: Box<InterfaceAccount<'info, Mint>>,
+    pub mint_y: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_x,
+        associated_token::authority = config,
+        associated_token::token_program = token_program,
+    )]
+    pub vault_x: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_y,
+        associated_token::authority = config,
+        associated_token::token_program = token_program,
+    )]
+    pub vault_y: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"mint", config.key().as_ref()],
+        bump = config.lp_bump,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Box<Account<'info, Oracle>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> Pool<'info> {
+    // LP-owned reserves as tracked by the program, tokens sent straight to the vaults don't count
+    pub fn reserves(&self) -> Result<(u64, u64)> {
+        Ok((self.config.reserve_x, self.config.reserve_y))
+    }
+
+    pub fn set_reserves(&mut self, (reserve_x, reserve_y): (u64, u64)) {
+        self.config.reserve_x = reserve_x;
+        self.config.reserve_y = reserve_y;
+    }
+
+    // What the vaults hold for LPs, tracked or not, see Config::vault_reserves
+    pub fn vault_reserves(&self) -> Result<(u64, u64)> {
+        self.config.vault_reserves(self.vault_x.amount, self.vault_y.amount)
+    }
+
+    pub fn curve(&self, now: i64) -> Result<Curve> {
+        Curve::new(&self.config, self.mint_x.decimals, self.mint_y.decimals, now)
+    }
+
+    // Fee changes are applied by the first operation charging a fee after they are due
+    pub fn apply_pending_fee(&mut self, now: i64) {
+        if let Some(fee) = self.config.apply_pending_fee(now) {
+            emit!(FeeUpdated {
+                config: self.config.key(),
+                fee,
+            });
+        }
+        if let Some(dynamic_fee) = self.config.apply_pending_dynamic_fee(now) {
+            emit!(DynamicFeeUpdated {
+                config: self.config.key(),
+                enabled: dynamic_fee.enabled,
+                min_fee: dynamic_fee.min_fee,
+                max_fee: dynamic_fee.max_fee,
+            });
+        }
+    }
+
+    // Records the price in effect until now, call before the reserves change
+    pub fn update_oracle(&mut self, curve: &Curve, (reserve_x, reserve_y): (u64, u64), clock: &Clock) -> Result<()> {
+        let spot_prices = curve.spot_prices(reserve_x, reserve_y)?;
+        self.config.update_price_accumulators(&mut self.oracle, spot_prices, clock);
+        Ok(())
+    }
+
+    // Amount credited to the receiver when `amount` of X (or Y) is sent
+    pub fn net_amount(&self, is_x: bool, amount: u64) -> Result<u64> {
+        let fee = transfer_fee(&self.mint(is_x), amount, false)?;
+        amount.checked_sub(fee).ok_or(AmmError::Underflow.into())
+    }
+
+    // Amount that has to be sent for `net` of X (or Y) to be credited to the receiver
+    pub fn gross_amount(&self, is_x: bool, net: u64) -> Result<u64> {
+        let fee = transfer_fee(&self.mint(is_x), net, true)?;
+        net.checked_add(fee).ok_or(AmmError::Overflow.into())
+    }
+
+    fn mint(&self, is_x: bool) -> AccountInfo<'info> {
+        match is_x {
+            true => self.mint_x.to_account_info(),
+            false => self.mint_y.to_account_info(),
+        }
+    }
+}
+
+// Token-2022 transfer fee for the current epoch, charged on `amount` or, with `inverse`,
+// needed on top of `amount` for it to arrive in full. Zero for mints without the extension.
+fn transfer_fee(mint: &AccountInfo, amount: u64, inverse: bool) -> Result<u64> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(0);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    let fee_config = match mint.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => fee_config,
+        Err(_) => return Ok(0),
+    };
+    let epoch = Clock::get()?.epoch;
+    let fee = match inverse {
+        true => fee_config.calculate_inverse_epoch_fee(epoch, amount),
+        false => fee_config.calculate_epoch_fee(epoch, amount),
+    };
+    fee.ok_or(AmmError::Overflow.into())
+}
+
+// transfer_checked that also works for TransferHook mints. The hook program, its
+// ExtraAccountMetaList and the extra accounts it lists are looked up in the context's
+// remaining accounts, which may hold those of both mints. Other mints ignore them.
+pub fn transfer_checked_with_hook<'info>(ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>, amount: u64, decimals: u8) -> Result<()> {
+    invoke_transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        &ctx.remaining_accounts,
+        amount,
+        decimals,
+        ctx.signer_seeds,
+    )?;
+    Ok(())
+}
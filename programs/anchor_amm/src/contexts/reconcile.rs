@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::assert_has_authority;
+use crate::errors::AmmError;
+use crate::events::ReservesSynced;
+use super::pool::*;
+
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    user: Signer<'info>,
+
+    pool: Pool<'info>,
+}
+
+impl<'info> SyncReserves<'info> {
+    // Adopts the vault balances as reserves, donations then count for LPs and move the price.
+    // Authority only, anyone could otherwise donate and sync to move the price.
+    pub fn sync(&mut self) -> Result<()> {
+        assert_has_authority!(self.pool.config, self.user);
+        require!(self.pool.config.flash_loan.is_none(), AmmError::FlashLoanActive);
+
+        let clock = Clock::get()?;
+        let curve = self.pool.curve(clock.unix_timestamp)?;
+        self.pool.update_oracle(&curve, self.pool.reserves()?, &clock)?;
+
+        let (reserve_x, reserve_y) = self.pool.vault_reserves()?;
+        self.pool.set_reserves((reserve_x, reserve_y));
+
+        emit!(ReservesSynced {
+            config: self.pool.config.key(),
+            reserve_x,
+            reserve_y,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::state::{CanonicalPool, Config, Factory};
+use crate::errors::AmmError;
+use crate::events::CanonicalPoolRegistered;
+
+#[derive(Accounts)]
+pub struct RegisterCanonical<'info> {
+    #[account(mut)]
+    admin: Signer<'info>,
+
+    #[account(
+        has_one = admin @ AmmError::InvalidAuthority,
+        seeds = [b"factory"],
+        bump = factory.bump,
+    )]
+    factory: Box<Account<'info, Factory>>,
+
+    mint_x: Box<InterfaceAccount<'info, Mint>>,
+    mint_y: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
+    )]
+    config: Box<Account<'info, Config>>,
+
+    // One per pair, chosen by the factory admin so nobody can squat it
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + CanonicalPool::INIT_SPACE,
+        seeds = [b"canonical", mint_x.key().as_ref(), mint_y.key().as_ref()],
+        bump
+    )]
+    canonical: Account<'info, CanonicalPool>,
+
+    system_program: Program<'info, System>,
+}
+
+impl<'info> RegisterCanonical<'info> {
+    pub fn register_canonical(&mut self, bumps: &RegisterCanonicalBumps) -> Result<()> {
+        self.canonical.set_inner(CanonicalPool {
+            config: self.config.key(),
+            bump: bumps.canonical,
+        });
+
+        emit!(CanonicalPoolRegistered {
+            config: self.config.key(),
+            mint_x: self.mint_x.key(),
+            mint_y: self.mint_y.key(),
+        });
+        Ok(())
+    }
+}
@@ -1,23 +1,31 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-    associated_token::AssociatedToken, 
-    token::{TransferChecked, transfer_checked}, 
-    token_interface::{ Mint, TokenAccount, TokenInterface}
+    associated_token::AssociatedToken,
+    token_interface::{TransferChecked, Mint, TokenAccount, TokenInterface}
 };
-use crate::{assert_not_locked, state::Config};
+use crate::{assert_not_paused, state::PAUSE_SWAP};
 use crate::errors::AmmError;
+use super::pool::*;
+use crate::events::Swapped;
 use crate::assert_non_zero;
-
-use constant_product_curve::{ConstantProduct, LiquidityPair};
+use crate::curves::{Curve, SwapQuote};
 
 #[derive(Accounts)]
-#[instruction(seed: u64)]
 pub struct Swap<'info> {
     #[account(mut)]
     user: Signer<'info>,
 
-    mint_x: InterfaceAccount<'info, Mint>,
-    mint_y: InterfaceAccount<'info, Mint>,
+    pool: Pool<'info>,
+
+    // init_if_needed can't reach into the Pool, these repeat its mints and token program
+    #[account(address = pool.mint_x.key())]
+    mint_x: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool.mint_y.key())]
+    mint_y: Box<InterfaceAccount<'info, Mint>>,
+    #[account(address = pool.token_program.key())]
+    token_program: Interface<'info, TokenInterface>,
+    associated_token_program: Program<'info, AssociatedToken>,
+    system_program: Program<'info, System>,
 
     #[account(
         init_if_needed,
@@ -26,7 +34,7 @@ pub struct Swap<'info> {
         associated_token::authority = user,
         associated_token::token_program = token_program,
     )]
-    user_ata_x: InterfaceAccount<'info, TokenAccount>,
+    user_ata_x: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         init_if_needed,
@@ -35,88 +43,127 @@ pub struct Swap<'info> {
         associated_token::authority = user,
         associated_token::token_program = token_program,
     )]
-    user_ata_y: InterfaceAccount<'info, TokenAccount>,
+    user_ata_y: Box<InterfaceAccount<'info, TokenAccount>>,
+}
 
-    #[account(
-        mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-        associated_token::token_program = token_program,
-    )]
-    vault_x: InterfaceAccount<'info, TokenAccount>,
+impl <'info> Swap<'info> {
+    pub fn swap(&mut self, mint_deposit:Pubkey, amount_in: u64, amount_out_min: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_SWAP, AmmError::SwapPaused);
+        assert_non_zero!([amount_in, amount_out_min]);
 
-    #[account(
-        mut,
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-        associated_token::token_program = token_program,
-    )]
-    vault_y: InterfaceAccount<'info, TokenAccount>,
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
 
-    #[account(
-        mut,
-        seeds = [b"mint", config.key().as_ref()],
-        bump = config.lp_bump,
-    )]
-    mint_lp: InterfaceAccount<'info, Mint>,
+        let (is_x, mint_withdraw) = match mint_deposit {
+            m if m == self.pool.mint_x.key() => (true, self.pool.mint_y.key()),
+            m if m == self.pool.mint_y.key() => (false, self.pool.mint_x.key()),
+            _ => return Err(AmmError::InvalidInputMint.into())
+        };
 
-    #[account(
-        has_one = mint_x,
-        has_one = mint_y,
-        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), seed.to_le_bytes().as_ref()],
-        bump = config.bump,
-    )]
-    config: Account<'info, Config>,
+        // Priced on what reaches the vault, slippage checked on what reaches the user
+        let received = self.pool.net_amount(is_x, amount_in)?;
+        let now = Clock::get()?.unix_timestamp;
+        self.pool.apply_pending_fee(now);
+        let curve = self.pool.curve(now)?;
+        let quote = curve.swap_exact_in(is_x, reserve_x, reserve_y, self.pool.mint_lp.supply, received, 0)?;
 
-    token_program: Interface<'info, TokenInterface>,
-    associated_token_program: Program<'info, AssociatedToken>,
-    system_program: Program<'info, System>,
-}
+        assert_non_zero!([quote.amount_in, quote.amount_out]);
+        require!(self.pool.net_amount(!is_x, quote.amount_out)? >= amount_out_min, AmmError::SlippageExceeded);
 
-impl <'info> Swap<'info> {
-    pub fn swap(&mut self, mint_deposit:Pubkey, amount_in: u64, amount_out_min: u64) -> Result<()> {
-        assert_not_locked!(self);
-        assert_non_zero!([amount_in, amount_out_min]);
+        self.settle_swap(&curve, is_x, mint_deposit, mint_withdraw, quote, (reserve_x, reserve_y), amount_in, remaining_accounts)
+    }
+
+    pub fn swap_exact_out(&mut self, mint_out: Pubkey, amount_out: u64, amount_in_max: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_SWAP, AmmError::SwapPaused);
+        assert_non_zero!([amount_out, amount_in_max]);
 
-        let mut curve = ConstantProduct::init(
-            self.vault_x.amount, 
-            self.vault_y.amount, 
-            self.mint_lp.supply, 
-            self.config.fee, 
-            None)
-            .map_err(AmmError::from)?;
-
-        let (p, mint_withdraw) = match mint_deposit {
-            m if m == self.mint_x.key() => (LiquidityPair::X, self.mint_y.key()),
-            m if m == self.mint_y.key() => (LiquidityPair::Y, self.mint_x.key()),
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
+
+        let (is_x, mint_deposit) = match mint_out {
+            m if m == self.pool.mint_y.key() => (true, self.pool.mint_x.key()),
+            m if m == self.pool.mint_x.key() => (false, self.pool.mint_y.key()),
             _ => return Err(AmmError::InvalidInputMint.into())
         };
 
-        let res = curve.swap(p, amount_in, amount_out_min).map_err(AmmError::from)?;
+        // The pool sends enough for amount_out to arrive, the user enough for the quote to arrive
+        let sent_out = self.pool.gross_amount(!is_x, amount_out)?;
+        let now = Clock::get()?.unix_timestamp;
+        self.pool.apply_pending_fee(now);
+        let curve = self.pool.curve(now)?;
+        let quote = curve.swap_exact_out(is_x, reserve_x, reserve_y, sent_out)?;
+        let amount_in = self.pool.gross_amount(is_x, quote.amount_in)?;
 
-        assert_non_zero!([res.deposit, res.withdraw]);
+        require!(amount_in <= amount_in_max, AmmError::SlippageExceeded);
 
-        self.deposit_token(mint_deposit, res.deposit)?;
-        self.withdraw_token(mint_withdraw, res.withdraw)?;
+        self.settle_swap(&curve, is_x, mint_deposit, mint_out, quote, (reserve_x, reserve_y), amount_in, remaining_accounts)
+    }
+
+    // Moves the tokens of a priced swap, sets the protocol share of the fee aside and emits the event.
+    // The quote is in vault terms, `amount_sent` is what leaves the user before transfer fees.
+    #[allow(clippy::too_many_arguments)]
+    fn settle_swap(
+        &mut self,
+        curve: &Curve,
+        is_x: bool,
+        mint_deposit: Pubkey,
+        mint_withdraw: Pubkey,
+        quote: SwapQuote,
+        (reserve_x, reserve_y): (u64, u64),
+        amount_sent: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        self.pool.update_oracle(curve, (reserve_x, reserve_y), &Clock::get()?)?;
+
+        // The LP share of the fee stays in the reserves, the protocol share is set aside
+        let protocol_fee = self.pool.config.accrue_protocol_fee(is_x, quote.fee)?;
+
+        self.deposit_token(mint_deposit, amount_sent, remaining_accounts)?;
+        self.withdraw_token(mint_withdraw, quote.amount_out, remaining_accounts)?;
+
+        let prices_before = curve.spot_prices(reserve_x, reserve_y)?;
+        let net_in = quote.amount_in.checked_sub(protocol_fee).ok_or(AmmError::Underflow)?;
+        let (reserve_x, reserve_y) = match is_x {
+            true => (reserve_x.checked_add(net_in).ok_or(AmmError::Overflow)?, reserve_y.checked_sub(quote.amount_out).ok_or(AmmError::Underflow)?),
+            false => (reserve_x.checked_sub(quote.amount_out).ok_or(AmmError::Underflow)?, reserve_y.checked_add(net_in).ok_or(AmmError::Overflow)?),
+        };
+        self.pool.set_reserves((reserve_x, reserve_y));
+
+        // Feeds the dynamic fee of the next swaps
+        if let (Some((before, _)), Some((after, _))) = (prices_before, curve.spot_prices(reserve_x, reserve_y)?) {
+            self.pool.config.record_price_move(before, after, Clock::get()?.unix_timestamp);
+        }
+
+        emit!(Swapped {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            mint_in: mint_deposit,
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            fee: quote.fee,
+            fee_rate: curve.fee,
+            protocol_fee,
+            reserve_x,
+            reserve_y,
+            lp_supply: self.pool.mint_lp.supply,
+        });
         Ok(())
-        
     }
 
     pub fn deposit_token(
         &mut self,
         mint_deposit: Pubkey,
         amount:u64,
+        remaining_accounts: &[AccountInfo<'info>],
     ) -> Result<()> {
 
         let mint;
         let (from, to) = match mint_deposit {
-            m if m == self.mint_x.key() => {
-                mint = self.mint_x.clone();
-                (self.user_ata_x.to_account_info(), self.vault_x.to_account_info())
+            m if m == self.pool.mint_x.key() => {
+                mint = self.pool.mint_x.clone();
+                (self.user_ata_x.to_account_info(), self.pool.vault_x.to_account_info())
             },
-            m if m == self.mint_y.key() => {
-                mint = self.mint_y.clone();
-                (self.user_ata_y.to_account_info(), self.vault_y.to_account_info())
+            m if m == self.pool.mint_y.key() => {
+                mint = self.pool.mint_y.clone();
+                (self.user_ata_y.to_account_info(), self.pool.vault_y.to_account_info())
             },
             _ => return Err(AmmError::InvalidInputMint.into())
         };
@@ -128,26 +175,27 @@ impl <'info> Swap<'info> {
             authority: self.user.to_account_info()
         };
 
-        let ctx = CpiContext::new(self.token_program.to_account_info(), account);
-
-        transfer_checked(ctx, amount, 6)
+        let ctx = CpiContext::new(self.pool.token_program.to_account_info(), account)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, mint.decimals)
     }
 
     pub fn withdraw_token(
         &mut self,
         mint_withdraw:Pubkey,
-        amount:u64
+        amount:u64,
+        remaining_accounts: &[AccountInfo<'info>],
     ) -> Result<()> {
 
         let mint;
         let (from, to) = match mint_withdraw {
-            m if m == self.mint_x.key() => {
-                mint = self.mint_x.clone();
-                (self.vault_x.to_account_info(), self.user_ata_x.to_account_info())
+            m if m == self.pool.mint_x.key() => {
+                mint = self.pool.mint_x.clone();
+                (self.pool.vault_x.to_account_info(), self.user_ata_x.to_account_info())
             },
-            m if m == self.mint_y.key() => {
-                mint = self.mint_y.clone();
-                (self.vault_y.to_account_info(), self.user_ata_y.to_account_info())
+            m if m == self.pool.mint_y.key() => {
+                mint = self.pool.mint_y.clone();
+                (self.pool.vault_y.to_account_info(), self.user_ata_y.to_account_info())
             },
             _ => return Err(AmmError::InvalidInputMint.into())
         };
@@ -156,23 +204,24 @@ impl <'info> Swap<'info> {
             from,
             mint: mint.to_account_info(),
             to,
-            authority: self.config.to_account_info()
+            authority: self.pool.config.to_account_info()
         };
 
-        let binding_mint_x = self.mint_x.to_account_info().key();
-        let binding_mint_y = self.mint_y.to_account_info().key();
-        let binding_seed = self.config.seed.to_le_bytes();
+        let binding_mint_x = self.pool.mint_x.to_account_info().key();
+        let binding_mint_y = self.pool.mint_y.to_account_info().key();
+        let binding_seed = self.pool.config.seed.to_le_bytes();
         let seeds: &[&[u8]; 5] = &[
             &b"amm"[..],
             &binding_mint_x.as_ref(),
             &binding_mint_y.as_ref(),
             &binding_seed.as_ref(),
-            &[self.config.bump],
+            &[self.pool.config.bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
-        let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), account, signer_seeds);
-        transfer_checked(ctx, amount, 6)
+        let ctx = CpiContext::new_with_signer(self.pool.token_program.to_account_info(), account, signer_seeds)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, mint.decimals)
 
     }
 
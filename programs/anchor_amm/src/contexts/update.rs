@@ -1,21 +1,25 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token_interface::Mint;
 
-use crate::state::Config;
+use crate::{assert_has_authority, state::{Config, CurveType, PAUSE_ALL}};
+use crate::curves::stable_swap::{MAX_AMP, MAX_AMP_CHANGE, MIN_AMP, MIN_RAMP_DURATION};
+use crate::errors::AmmError;
+use crate::events::{AmpRampStarted, AmpRampStopped, PauseFlagsUpdated, PauserUpdated, PoolLocked, PoolUnlocked};
 
 #[derive(Accounts)]
-#[instruction(seed: u64)]
 pub struct Update<'info> {
     #[account(mut)]
-    admin: Signer<'info>,
+    user: Signer<'info>,
 
     mint_x: InterfaceAccount<'info, Mint>,
     mint_y: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
-        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), seed.to_le_bytes().as_ref()],
-        bump
+        has_one = mint_x,
+        has_one = mint_y,
+        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), config.seed.to_le_bytes().as_ref()],
+        bump = config.bump,
     )]
     config: Account<'info, Config>,
 
@@ -24,12 +28,105 @@ pub struct Update<'info> {
 
 impl<'info> Update<'info> {
     pub fn lock(&mut self) -> Result<()> {
-        self.config.locked = true;
+        if self.config.pauser != Some(self.user.key()) {
+            assert_has_authority!(self);
+        }
+        self.config.paused = PAUSE_ALL;
+
+        emit!(PoolLocked {
+            config: self.config.key(),
+            user: self.user.key(),
+        });
         Ok(())
     }
 
     pub fn unlock(&mut self) -> Result<()> {
-        self.config.locked = false;
+        assert_has_authority!(self);
+        self.config.paused = 0;
+
+        emit!(PoolUnlocked {
+            config: self.config.key(),
+            user: self.user.key(),
+        });
+        Ok(())
+    }
+
+    pub fn set_pause_flags(&mut self, paused: u8) -> Result<()> {
+        require!(paused & !PAUSE_ALL == 0, AmmError::InvalidPauseFlags);
+
+        // The pauser may only pause more operations, lifting a pause needs the authority
+        let only_adds_flags = paused & self.config.paused == self.config.paused;
+        if !(only_adds_flags && self.config.pauser == Some(self.user.key())) {
+            assert_has_authority!(self);
+        }
+        self.config.paused = paused;
+
+        emit!(PauseFlagsUpdated {
+            config: self.config.key(),
+            user: self.user.key(),
+            paused,
+        });
+        Ok(())
+    }
+
+    pub fn ramp_amp(&mut self, target_amp: u64, ramp_end_ts: i64) -> Result<()> {
+        assert_has_authority!(self);
+        require!(self.config.curve_type == CurveType::StableSwap, AmmError::UnsupportedCurve);
+        require!((MIN_AMP..=MAX_AMP).contains(&target_amp), AmmError::InvalidAmp);
+
+        let now = Clock::get()?.unix_timestamp;
+        let initial_amp = self.config.amp(now);
+        require!(now >= self.config.ramp_end_ts, AmmError::InvalidAmpRamp);
+        require!(ramp_end_ts >= now.saturating_add(MIN_RAMP_DURATION), AmmError::InvalidAmpRamp);
+        require!(
+            target_amp <= initial_amp.saturating_mul(MAX_AMP_CHANGE) && initial_amp <= target_amp.saturating_mul(MAX_AMP_CHANGE),
+            AmmError::InvalidAmpRamp
+        );
+
+        self.config.initial_amp = initial_amp;
+        self.config.target_amp = target_amp;
+        self.config.ramp_start_ts = now;
+        self.config.ramp_end_ts = ramp_end_ts;
+
+        emit!(AmpRampStarted {
+            config: self.config.key(),
+            user: self.user.key(),
+            initial_amp,
+            target_amp,
+            ramp_start_ts: now,
+            ramp_end_ts,
+        });
+        Ok(())
+    }
+
+    pub fn stop_ramp_amp(&mut self) -> Result<()> {
+        assert_has_authority!(self);
+        require!(self.config.curve_type == CurveType::StableSwap, AmmError::UnsupportedCurve);
+
+        let now = Clock::get()?.unix_timestamp;
+        let amp = self.config.amp(now);
+        self.config.initial_amp = amp;
+        self.config.target_amp = amp;
+        self.config.ramp_start_ts = now;
+        self.config.ramp_end_ts = now;
+
+        emit!(AmpRampStopped {
+            config: self.config.key(),
+            user: self.user.key(),
+            amp,
+        });
+        Ok(())
+    }
+
+    pub fn set_pauser(&mut self, pauser: Option<Pubkey>) -> Result<()> {
+        assert_has_authority!(self);
+        self.config.pauser = pauser;
+
+        emit!(PauserUpdated {
+            config: self.config.key(),
+            user: self.user.key(),
+            pauser,
+        });
         Ok(())
     }
 }
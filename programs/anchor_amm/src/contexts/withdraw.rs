@@ -1,140 +1,190 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-    associated_token::AssociatedToken, 
-    token::{TransferChecked, transfer_checked, Burn, burn}, 
-    token_interface::{ Mint, TokenAccount, TokenInterface}
+    token_interface::{TransferChecked, Burn, burn, TokenAccount}
 };
-use crate::{assert_not_locked, state::Config};
+use crate::{assert_not_paused, state::{PAUSE_SWAP, PAUSE_WITHDRAW}};
 use crate::errors::AmmError;
+use super::pool::*;
+use crate::events::LiquidityRemoved;
 use crate::assert_non_zero;
 
-use constant_product_curve::ConstantProduct;
 
 #[derive(Accounts)]
-#[instruction(seed: u64)]
 pub struct Withdraw<'info> {
     #[account(mut)]
     user: Signer<'info>,
 
-    mint_x: InterfaceAccount<'info, Mint>,
-    mint_y: InterfaceAccount<'info, Mint>,
+    pool: Pool<'info>,
 
     #[account(
         mut,
-        associated_token::mint = mint_x,
+        associated_token::mint = pool.mint_x,
         associated_token::authority = user,
-        associated_token::token_program = token_program,
+        associated_token::token_program = pool.token_program,
     )]
-    user_ata_x: InterfaceAccount<'info, TokenAccount>,
+    user_ata_x: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_y,
+        associated_token::mint = pool.mint_y,
         associated_token::authority = user,
-        associated_token::token_program = token_program,
+        associated_token::token_program = pool.token_program,
     )]
-    user_ata_y: InterfaceAccount<'info, TokenAccount>,
+    user_ata_y: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_lp,
+        associated_token::mint = pool.mint_lp,
         associated_token::authority = user,
-        associated_token::token_program = token_program,
+        associated_token::token_program = pool.token_program,
     )]
-    user_ata_lp: InterfaceAccount<'info, TokenAccount>,
+    user_ata_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+}
 
-    #[account(
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-        associated_token::token_program = token_program,
-    )]
-    vault_x: InterfaceAccount<'info, TokenAccount>,
+impl <'info> Withdraw<'info> {
+    pub fn withdraw(&mut self, amount:u64, min_x: u64, min_y: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_WITHDRAW, AmmError::WithdrawPaused);
+        assert_non_zero!([amount, min_x, min_y]);
 
-    #[account(
-        associated_token::mint = mint_x,
-        associated_token::authority = config,
-        associated_token::token_program = token_program,
-    )]
-    vault_y: InterfaceAccount<'info, TokenAccount>,
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
 
-    #[account(
-        mut,
-        seeds = [b"mint", config.key().as_ref()],
-        bump = config.lp_bump,
-    )]
-    mint_lp: InterfaceAccount<'info, Mint>,
+        let clock = Clock::get()?;
+        let curve = self.pool.curve(clock.unix_timestamp)?;
+        self.pool.update_oracle(&curve, (reserve_x, reserve_y), &clock)?;
 
-    #[account(
-        has_one = mint_x,
-        has_one = mint_y,
-        seeds = [b"amm".as_ref(), mint_x.key().as_ref(), mint_y.key().as_ref(), seed.to_le_bytes().as_ref()],
-        bump = config.bump,
-    )]
-    config: Account<'info, Config>,
+        let (x, y) = curve.withdraw_amounts(reserve_x, reserve_y, self.pool.mint_lp.supply, amount)?;
+        
+        // Slippage is checked on what reaches the user after transfer fees
+        require!(
+            self.pool.net_amount(true, x)? >= min_x && self.pool.net_amount(false, y)? >= min_y,
+            AmmError::SlippageExceeded
+        );
 
-    token_program: Interface<'info, TokenInterface>,
-    associated_token_program: Program<'info, AssociatedToken>,
-    system_program: Program<'info, System>,
-}
+        self.withdraw_tokens(true, x, remaining_accounts)?;
+        self.withdraw_tokens(false, y, remaining_accounts)?;
+        self.burn_lp_tokens(amount)?;
+
+        let reserve_x = reserve_x.checked_sub(x).ok_or(AmmError::Underflow)?;
+        let reserve_y = reserve_y.checked_sub(y).ok_or(AmmError::Underflow)?;
+        self.pool.set_reserves((reserve_x, reserve_y));
+
+        emit!(LiquidityRemoved {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            amount_x: x,
+            amount_y: y,
+            lp_burned: amount,
+            reserve_x,
+            reserve_y,
+            lp_supply: self.pool.mint_lp.supply.checked_sub(amount).ok_or(AmmError::Underflow)?,
+        });
+        Ok(())
+    }
 
-impl <'info> Withdraw<'info> {
-    pub fn withdraw(&mut self, amount:u64, min_x: u64, min_y: u64) -> Result<()> {
-        require!(!self.config.locked, AmmError::PoolLocked);
-        assert_non_zero!([amount, min_x, min_y]);
-        assert_not_locked!(self);
+    pub fn withdraw_single(&mut self, amount: u64, mint_out: Pubkey, min_out: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        assert_not_paused!(self.pool, PAUSE_WITHDRAW, AmmError::WithdrawPaused);
+        // The unwanted side goes through the curve like a regular swap
+        assert_not_paused!(self.pool, PAUSE_SWAP, AmmError::SwapPaused);
+        assert_non_zero!([amount, min_out]);
 
-        let amounts = ConstantProduct::xy_withdraw_amounts_from_l(self.vault_x.amount, self.vault_y.amount, self.mint_lp.supply, amount, 6).map_err(AmmError::from)?;
-        let (x, y) = (amounts.x, amounts.y);
-        
-        require!(x >= min_x && y >= min_y, AmmError::SlippageExceeded);
+        let is_x = match mint_out {
+            m if m == self.pool.mint_x.key() => true,
+            m if m == self.pool.mint_y.key() => false,
+            _ => return Err(AmmError::InvalidInputMint.into())
+        };
+
+        let (reserve_x, reserve_y) = self.pool.reserves()?;
+
+        let clock = Clock::get()?;
+        self.pool.apply_pending_fee(clock.unix_timestamp);
+        let curve = self.pool.curve(clock.unix_timestamp)?;
+        self.pool.update_oracle(&curve, (reserve_x, reserve_y), &clock)?;
 
-        self.withdraw_tokens(true, x)?;
-        self.withdraw_tokens(false, y)?;
-        self.burn_lp_tokens(amount)
+        let lp_supply = self.pool.mint_lp.supply.checked_sub(amount).ok_or(AmmError::Underflow)?;
+        let (x, y) = curve.withdraw_amounts(reserve_x, reserve_y, self.pool.mint_lp.supply, amount)?;
+        let reserve_x = reserve_x.checked_sub(x).ok_or(AmmError::Underflow)?;
+        let reserve_y = reserve_y.checked_sub(y).ok_or(AmmError::Underflow)?;
+
+        // Swap the other side's share back into the pool after the proportional exit
+        let (kept, swapped) = if is_x { (x, y) } else { (y, x) };
+        let quote = curve.swap_exact_in(!is_x, reserve_x, reserve_y, lp_supply, swapped, 0)?;
+        let protocol_fee = self.pool.config.accrue_protocol_fee(!is_x, quote.fee)?;
+        let amount_out = kept.checked_add(quote.amount_out).ok_or(AmmError::Overflow)?;
+
+        // Slippage is checked on what reaches the user after transfer fees
+        require!(self.pool.net_amount(is_x, amount_out)? >= min_out, AmmError::SlippageExceeded);
+
+        self.withdraw_tokens(is_x, amount_out, remaining_accounts)?;
+        self.burn_lp_tokens(amount)?;
+
+        let prices_before = curve.spot_prices(reserve_x, reserve_y)?;
+        let net_in = swapped.checked_sub(protocol_fee).ok_or(AmmError::Underflow)?;
+        let (amount_x, amount_y, reserve_x, reserve_y) = match is_x {
+            true => (amount_out, 0, reserve_x.checked_sub(quote.amount_out).ok_or(AmmError::Underflow)?, reserve_y.checked_add(net_in).ok_or(AmmError::Overflow)?),
+            false => (0, amount_out, reserve_x.checked_add(net_in).ok_or(AmmError::Overflow)?, reserve_y.checked_sub(quote.amount_out).ok_or(AmmError::Underflow)?),
+        };
+        self.pool.set_reserves((reserve_x, reserve_y));
+
+        // Feeds the dynamic fee of the next swaps
+        if let (Some((before, _)), Some((after, _))) = (prices_before, curve.spot_prices(reserve_x, reserve_y)?) {
+            self.pool.config.record_price_move(before, after, clock.unix_timestamp);
+        }
+
+        emit!(LiquidityRemoved {
+            config: self.pool.config.key(),
+            user: self.user.key(),
+            amount_x,
+            amount_y,
+            lp_burned: amount,
+            reserve_x,
+            reserve_y,
+            lp_supply,
+        });
+        Ok(())
     }
 
-    pub fn withdraw_tokens(&mut self, is_x: bool, amount: u64) -> Result<()> {
+    pub fn withdraw_tokens(&mut self, is_x: bool, amount: u64, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
 
-        let binding_mint_x = self.mint_x.to_account_info().key();
-        let binding_mint_y = self.mint_y.to_account_info().key();
-        let binding_seed = self.config.seed.to_le_bytes();
+        let binding_mint_x = self.pool.mint_x.to_account_info().key();
+        let binding_mint_y = self.pool.mint_y.to_account_info().key();
+        let binding_seed = self.pool.config.seed.to_le_bytes();
         let seeds = &[
             &b"amm"[..],
             &binding_mint_x.as_ref(),
             &binding_mint_y.as_ref(),
             &binding_seed.as_ref(),
-            &[self.config.bump],
+            &[self.pool.config.bump],
         ];
         let signer_seeds = &[&seeds[..]];
 
         let (mint, decimals, vault, ata) = match is_x {
-            true => (self.mint_x.to_account_info(), self.mint_x.decimals, self.vault_x.to_account_info(), self.user_ata_x.to_account_info()),
-            false => (self.mint_y.to_account_info(), self.mint_y.decimals, self.vault_y.to_account_info(), self.user_ata_y.to_account_info()),
+            true => (self.pool.mint_x.to_account_info(), self.pool.mint_x.decimals, self.pool.vault_x.to_account_info(), self.user_ata_x.to_account_info()),
+            false => (self.pool.mint_y.to_account_info(), self.pool.mint_y.decimals, self.pool.vault_y.to_account_info(), self.user_ata_y.to_account_info()),
         };
 
         let accounts = TransferChecked {
             from: vault,
             to: ata,
-            authority: self.config.to_account_info(),
+            authority: self.pool.config.to_account_info(),
             mint: mint
         };
 
-        let ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), accounts, signer_seeds);
-
-        transfer_checked(ctx, amount, decimals)?;
+        let ctx = CpiContext::new_with_signer(self.pool.token_program.to_account_info(), accounts, signer_seeds)
+            .with_remaining_accounts(remaining_accounts.to_vec());
+        transfer_checked_with_hook(ctx, amount, decimals)?;
 
         Ok(())
     }
 
     pub fn burn_lp_tokens(&mut self, amount: u64) -> Result<()> {
         let accounts = Burn {
-            mint: self.mint_lp.to_account_info(),
+            mint: self.pool.mint_lp.to_account_info(),
             from: self.user_ata_lp.to_account_info(),
             authority: self.user.to_account_info(),
         };
 
         let ctx = CpiContext::new(
-            self.token_program.to_account_info(),
+            self.pool.token_program.to_account_info(),
             accounts
         );
 
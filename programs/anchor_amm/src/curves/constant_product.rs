@@ -0,0 +1,60 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::AmmError;
+use super::{SwapQuote, U256};
+
+// Input needed to take exactly amount_out from the pool. Both the curve input and the
+// fee gross-up are rounded up so the invariant never decreases.
+pub fn amount_in_for_exact_out(reserve_in: u64, reserve_out: u64, amount_out: u64, fee: u16) -> Result<SwapQuote> {
+    require!(reserve_in > 0 && reserve_out > 0, AmmError::ZeroBalance);
+    require!(amount_out < reserve_out, AmmError::InsufficientBalance);
+
+    let numerator = (reserve_in as u128)
+        .checked_mul(amount_out as u128)
+        .ok_or(AmmError::Overflow)?;
+    let denominator = (reserve_out - amount_out) as u128;
+    let net_in = u64::try_from(numerator.div_ceil(denominator)).map_err(|_| AmmError::Overflow)?;
+
+    let amount_in = gross_up_fee(net_in, fee)?;
+
+    Ok(SwapQuote {
+        amount_in,
+        amount_out,
+        fee: amount_in - net_in,
+    })
+}
+
+// Smallest input that still leaves `net_in` after the fee is taken
+pub fn gross_up_fee(net_in: u64, fee: u16) -> Result<u64> {
+    let fee_denominator = 10_000u128.checked_sub(fee as u128).ok_or(AmmError::InvalidFee)?;
+    let amount_in = (net_in as u128)
+        .checked_mul(10_000)
+        .ok_or(AmmError::Overflow)?
+        .div_ceil(fee_denominator);
+    u64::try_from(amount_in).map_err(|_| AmmError::Overflow.into())
+}
+
+// Q64.64 prices of X in Y and Y in X from the reserve ratio
+pub fn spot_prices(reserve_x: u64, reserve_y: u64) -> Option<(u128, u128)> {
+    if reserve_x == 0 || reserve_y == 0 {
+        return None;
+    }
+    let price_x = ((reserve_y as u128) << 64) / reserve_x as u128;
+    let price_y = ((reserve_x as u128) << 64) / reserve_y as u128;
+    Some((price_x, price_y))
+}
+
+// Part of a single-sided `amount_in` to swap so the remainder and the swap output match the
+// post-swap reserve ratio. Solves s^2 (1 - f) + s R (2 - f) - a R = 0 with the fee in bps,
+// rounded down so the user never swaps more than needed.
+pub fn optimal_swap_in(reserve_in: u64, amount_in: u64, fee: u16) -> Result<u64> {
+    require!(reserve_in > 0, AmmError::ZeroBalance);
+    let (reserve, amount) = (U256::from(reserve_in), U256::from(amount_in));
+    let two_minus_fee = U256::from(20_000 - fee as u64);
+    let one_minus_fee = U256::from(10_000 - fee as u64);
+
+    let discriminant = reserve * two_minus_fee * two_minus_fee + U256::from(40_000u64) * one_minus_fee * amount;
+    let root = (reserve * discriminant).integer_sqrt();
+    let swap_in = (root - reserve * two_minus_fee) / (U256::from(2u8) * one_minus_fee);
+    Ok(swap_in.min(amount).as_u64())
+}
@@ -0,0 +1,269 @@
+use anchor_lang::prelude::*;
+use constant_product_curve::{ConstantProduct, LiquidityPair};
+
+use crate::errors::AmmError;
+use crate::state::{Config, CurveType};
+
+pub mod constant_product;
+pub mod stable_swap;
+pub mod weighted;
+mod u256;
+pub use u256::U256;
+
+// A priced swap, fee included in amount_in
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+}
+
+// A single-sided join or exit: token amount, LP amount and the swap fee charged
+pub struct SingleSidedQuote {
+    pub amount: u64,
+    pub lp_amount: u64,
+    pub fee: u64,
+}
+
+// Pricing for one pool at one point in time. StableSwap balances are normalized to the
+// larger of the two mint decimals so pegged mints with different decimals trade at par.
+// Weighted pools price on raw balances, the weights already carry the value split.
+pub struct Curve {
+    pub curve_type: CurveType,
+    pub fee: u16,
+    pub amp: u64,
+    pub weight_x: u16,
+    pub weight_y: u16,
+    pub scale_x: u128,
+    pub scale_y: u128,
+}
+
+impl Curve {
+    pub fn new(config: &Config, decimals_x: u8, decimals_y: u8, now: i64) -> Result<Self> {
+        let (scale_x, scale_y) = match config.curve_type {
+            CurveType::StableSwap => {
+                let decimals = decimals_x.max(decimals_y);
+                (scale(decimals - decimals_x)?, scale(decimals - decimals_y)?)
+            },
+            _ => (1, 1),
+        };
+        Ok(Self {
+            curve_type: config.curve_type,
+            fee: config.swap_fee(now),
+            amp: config.amp(now),
+            weight_x: config.weight_x,
+            weight_y: 10_000 - config.weight_x,
+            scale_x,
+            scale_y,
+        })
+    }
+
+    fn fee_amount(&self, amount_in: u64) -> Result<u64> {
+        let fee = (amount_in as u128)
+            .checked_mul(self.fee as u128)
+            .ok_or(AmmError::Overflow)?
+            / 10_000;
+        Ok(fee as u64)
+    }
+
+    fn normalize(&self, is_x: bool, amount: u64) -> U256 {
+        let scale = if is_x { self.scale_x } else { self.scale_y };
+        U256::from(amount) * U256::from(scale)
+    }
+
+    fn denormalize(&self, is_x: bool, amount: U256, round_up: bool) -> Result<u64> {
+        let scale = U256::from(if is_x { self.scale_x } else { self.scale_y });
+        let mut value = amount / scale;
+        if round_up && !(amount % scale).is_zero() {
+            value += U256::one();
+        }
+        require!(value <= U256::from(u64::MAX), AmmError::Overflow);
+        Ok(value.as_u64())
+    }
+
+    pub fn swap_exact_in(&self, is_x_in: bool, reserve_x: u64, reserve_y: u64, lp_supply: u64, amount_in: u64, min_out: u64) -> Result<SwapQuote> {
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                let mut curve = ConstantProduct::init(reserve_x, reserve_y, lp_supply, self.fee, None)
+                    .map_err(AmmError::from)?;
+                let p = if is_x_in { LiquidityPair::X } else { LiquidityPair::Y };
+                let res = curve.swap(p, amount_in, min_out).map_err(AmmError::from)?;
+                Ok(SwapQuote {
+                    amount_in: res.deposit,
+                    amount_out: res.withdraw,
+                    fee: self.fee_amount(res.deposit)?,
+                })
+            },
+            CurveType::StableSwap => {
+                let fee = self.fee_amount(amount_in)?;
+                let (reserve_in, reserve_out) = self.oriented(is_x_in, reserve_x, reserve_y);
+                let net_in = self.normalize(is_x_in, amount_in - fee);
+                let out = stable_swap::amount_out(self.amp, reserve_in, reserve_out, net_in)?;
+                let amount_out = self.denormalize(!is_x_in, out, false)?;
+                require!(amount_out >= min_out, AmmError::SlippageExceeded);
+                Ok(SwapQuote { amount_in, amount_out, fee })
+            },
+            CurveType::Weighted => {
+                let fee = self.fee_amount(amount_in)?;
+                let ((reserve_in, weight_in), (reserve_out, weight_out)) = self.weighted_sides(is_x_in, reserve_x, reserve_y);
+                let amount_out = weighted::amount_out(reserve_in, weight_in, reserve_out, weight_out, amount_in - fee)?;
+                require!(amount_out >= min_out, AmmError::SlippageExceeded);
+                Ok(SwapQuote { amount_in, amount_out, fee })
+            },
+        }
+    }
+
+    pub fn swap_exact_out(&self, is_x_in: bool, reserve_x: u64, reserve_y: u64, amount_out: u64) -> Result<SwapQuote> {
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                let (reserve_in, reserve_out) = if is_x_in { (reserve_x, reserve_y) } else { (reserve_y, reserve_x) };
+                constant_product::amount_in_for_exact_out(reserve_in, reserve_out, amount_out, self.fee)
+            },
+            CurveType::StableSwap => {
+                let (reserve_in, reserve_out) = self.oriented(is_x_in, reserve_x, reserve_y);
+                let net_in = stable_swap::net_amount_in(self.amp, reserve_in, reserve_out, self.normalize(!is_x_in, amount_out))?;
+                let net_in = self.denormalize(is_x_in, net_in, true)?;
+                let amount_in = constant_product::gross_up_fee(net_in, self.fee)?;
+                Ok(SwapQuote { amount_in, amount_out, fee: amount_in - net_in })
+            },
+            CurveType::Weighted => {
+                let ((reserve_in, weight_in), (reserve_out, weight_out)) = self.weighted_sides(is_x_in, reserve_x, reserve_y);
+                let net_in = weighted::net_amount_in(reserve_in, weight_in, reserve_out, weight_out, amount_out)?;
+                let amount_in = constant_product::gross_up_fee(net_in, self.fee)?;
+                Ok(SwapQuote { amount_in, amount_out, fee: amount_in - net_in })
+            },
+        }
+    }
+
+    // LP minted for depositing only `amount_in` of one side. Only the part that would have
+    // to be swapped to match the pool pays the fee.
+    pub fn deposit_single(&self, is_x_in: bool, reserve_x: u64, reserve_y: u64, lp_supply: u64, amount_in: u64) -> Result<SingleSidedQuote> {
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                // Swap the optimal portion, then join proportionally with both sides
+                let (reserve_in, reserve_out) = if is_x_in { (reserve_x, reserve_y) } else { (reserve_y, reserve_x) };
+                let swap_in = constant_product::optimal_swap_in(reserve_in, amount_in, self.fee)?;
+                let quote = self.swap_exact_in(is_x_in, reserve_x, reserve_y, lp_supply, swap_in, 0)?;
+
+                let reserve_in = reserve_in.checked_add(swap_in).ok_or(AmmError::Overflow)?;
+                let reserve_out = reserve_out.checked_sub(quote.amount_out).ok_or(AmmError::Underflow)?;
+                let lp_amount = proportional(lp_supply, amount_in - swap_in, reserve_in, false)?
+                    .min(proportional(lp_supply, quote.amount_out, reserve_out, false)?);
+                Ok(SingleSidedQuote { amount: amount_in, lp_amount, fee: quote.fee })
+            },
+            CurveType::StableSwap => {
+                // At par the other side's share of the pool is what would have to be swapped
+                let (reserve_in, reserve_out) = self.oriented(is_x_in, reserve_x, reserve_y);
+                let total = reserve_in.checked_add(reserve_out).ok_or(AmmError::Overflow)?;
+                require!(!total.is_zero(), AmmError::ZeroBalance);
+                let taxable = (U256::from(amount_in) * reserve_out + total - U256::one()) / total;
+                let fee = (taxable * U256::from(self.fee) + U256::from(9_999u64)) / U256::from(10_000u64);
+                let fee = fee.as_u64();
+
+                let d0 = stable_swap::compute_d(self.amp, reserve_in, reserve_out)?;
+                let net_in = self.normalize(is_x_in, amount_in - fee);
+                let d1 = stable_swap::compute_d(self.amp, reserve_in.checked_add(net_in).ok_or(AmmError::Overflow)?, reserve_out)?;
+                require!(!d0.is_zero(), AmmError::ZeroBalance);
+
+                let lp_amount = U256::from(lp_supply) * d1.saturating_sub(d0) / d0;
+                require!(lp_amount <= U256::from(u64::MAX), AmmError::Overflow);
+                Ok(SingleSidedQuote { amount: amount_in, lp_amount: lp_amount.as_u64(), fee })
+            },
+            CurveType::Weighted => {
+                let ((reserve_in, weight_in), _) = self.weighted_sides(is_x_in, reserve_x, reserve_y);
+                let (lp_amount, fee) = weighted::lp_out_for_single_in(reserve_in, weight_in, lp_supply, amount_in, self.fee)?;
+                Ok(SingleSidedQuote { amount: amount_in, lp_amount, fee })
+            },
+        }
+    }
+
+    // LP backing the first deposit: sqrt(x * y) for constant product, D for StableSwap and
+    // the weighted geometric mean for weighted pools
+    pub fn initial_liquidity(&self, amount_x: u64, amount_y: u64) -> Result<u64> {
+        match self.curve_type {
+            CurveType::ConstantProduct => Ok((U256::from(amount_x) * U256::from(amount_y)).integer_sqrt().as_u64()),
+            CurveType::StableSwap => {
+                let d = stable_swap::compute_d(self.amp, self.normalize(true, amount_x), self.normalize(false, amount_y))?;
+                require!(d <= U256::from(u64::MAX), AmmError::Overflow);
+                Ok(d.as_u64())
+            },
+            CurveType::Weighted => weighted::invariant(amount_x, self.weight_x, amount_y, self.weight_y),
+        }
+    }
+
+    // Token amounts owed for minting `amount` LP, rounded up in favour of the pool
+    pub fn deposit_amounts(&self, reserve_x: u64, reserve_y: u64, lp_supply: u64, amount: u64) -> Result<(u64, u64)> {
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                let amounts = ConstantProduct::xy_deposit_amounts_from_l(reserve_x, reserve_y, lp_supply, amount, 6).map_err(AmmError::from)?;
+                Ok((amounts.x, amounts.y))
+            },
+            // Proportional joins leave invariant/supply unchanged whatever the invariant
+            CurveType::StableSwap | CurveType::Weighted => Ok((
+                proportional(reserve_x, amount, lp_supply, true)?,
+                proportional(reserve_y, amount, lp_supply, true)?,
+            )),
+        }
+    }
+
+    // Token amounts paid out for burning `amount` LP, rounded down in favour of the pool
+    pub fn withdraw_amounts(&self, reserve_x: u64, reserve_y: u64, lp_supply: u64, amount: u64) -> Result<(u64, u64)> {
+        match self.curve_type {
+            CurveType::ConstantProduct => {
+                let amounts = ConstantProduct::xy_withdraw_amounts_from_l(reserve_x, reserve_y, lp_supply, amount, 6).map_err(AmmError::from)?;
+                Ok((amounts.x, amounts.y))
+            },
+            CurveType::StableSwap | CurveType::Weighted => Ok((
+                proportional(reserve_x, amount, lp_supply, false)?,
+                proportional(reserve_y, amount, lp_supply, false)?,
+            )),
+        }
+    }
+
+    // Q64.64 marginal prices of X in Y and Y in X, None while a side is empty
+    pub fn spot_prices(&self, reserve_x: u64, reserve_y: u64) -> Result<Option<(u128, u128)>> {
+        if reserve_x == 0 || reserve_y == 0 {
+            return Ok(None);
+        }
+        match self.curve_type {
+            CurveType::ConstantProduct => Ok(constant_product::spot_prices(reserve_x, reserve_y)),
+            CurveType::StableSwap => {
+                let (x, y) = (self.normalize(true, reserve_x), self.normalize(false, reserve_y));
+                // Normalized prices are converted back to raw units of each mint
+                let price_x = stable_swap::spot_price(self.amp, x, y)? * U256::from(self.scale_x) / U256::from(self.scale_y);
+                let price_y = stable_swap::spot_price(self.amp, y, x)? * U256::from(self.scale_y) / U256::from(self.scale_x);
+                require!(price_x.bits() <= 128 && price_y.bits() <= 128, AmmError::Overflow);
+                Ok(Some((price_x.as_u128(), price_y.as_u128())))
+            },
+            CurveType::Weighted => Ok(Some((
+                weighted::spot_price(reserve_x, self.weight_x, reserve_y, self.weight_y)?,
+                weighted::spot_price(reserve_y, self.weight_y, reserve_x, self.weight_x)?,
+            ))),
+        }
+    }
+
+    fn weighted_sides(&self, is_x_in: bool, reserve_x: u64, reserve_y: u64) -> ((u64, u16), (u64, u16)) {
+        let (x, y) = ((reserve_x, self.weight_x), (reserve_y, self.weight_y));
+        if is_x_in { (x, y) } else { (y, x) }
+    }
+
+    fn oriented(&self, is_x_in: bool, reserve_x: u64, reserve_y: u64) -> (U256, U256) {
+        let (x, y) = (self.normalize(true, reserve_x), self.normalize(false, reserve_y));
+        if is_x_in { (x, y) } else { (y, x) }
+    }
+}
+
+// reserve * amount / supply
+pub fn proportional(reserve: u64, amount: u64, supply: u64, round_up: bool) -> Result<u64> {
+    require!(supply > 0, AmmError::ZeroBalance);
+    let numerator = (reserve as u128).checked_mul(amount as u128).ok_or(AmmError::Overflow)?;
+    let value = match round_up {
+        true => numerator.div_ceil(supply as u128),
+        false => numerator / supply as u128,
+    };
+    u64::try_from(value).map_err(|_| AmmError::Overflow.into())
+}
+
+// 10^gap, bounded at pool creation by MAX_DECIMALS_GAP
+fn scale(gap: u8) -> Result<u128> {
+    10u128.checked_pow(gap as u32).ok_or(AmmError::Overflow.into())
+}
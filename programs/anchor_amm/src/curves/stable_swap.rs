@@ -0,0 +1,203 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::AmmError;
+use super::U256;
+
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+// A ramp can move the amplification at most this many times up or down
+pub const MAX_AMP_CHANGE: u64 = 10;
+pub const MIN_RAMP_DURATION: i64 = 86_400;
+// Largest difference between the two mints' decimals a pool can normalize away
+pub const MAX_DECIMALS_GAP: u8 = 18;
+
+const N_COINS: u64 = 2;
+const MAX_ITERATIONS: usize = 255;
+
+// All functions below work on balances normalized to a common precision, see Curve
+
+fn ann(amp: u64) -> U256 {
+    U256::from(amp) * U256::from(N_COINS * N_COINS)
+}
+
+fn converged(a: U256, b: U256) -> bool {
+    let diff = if a > b { a - b } else { b - a };
+    diff <= U256::one()
+}
+
+// Invariant D of the two balances, solved with Newton's method as in Curve's get_D
+pub fn compute_d(amp: u64, x: U256, y: U256) -> Result<U256> {
+    let sum = x.checked_add(y).ok_or(AmmError::Overflow)?;
+    if sum.is_zero() {
+        return Ok(U256::zero());
+    }
+    require!(!x.is_zero() && !y.is_zero(), AmmError::ZeroBalance);
+
+    let ann = ann(amp);
+    let n = U256::from(N_COINS);
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        // d_p = D^(n+1) / (n^n * x * y)
+        let d_p = d.checked_mul(d).ok_or(AmmError::Overflow)? / (x * n);
+        let d_p = d_p.checked_mul(d).ok_or(AmmError::Overflow)? / (y * n);
+
+        let previous = d;
+        let numerator = ann.checked_mul(sum)
+            .and_then(|v| v.checked_add(d_p * n))
+            .and_then(|v| v.checked_mul(d))
+            .ok_or(AmmError::Overflow)?;
+        let denominator = (ann - U256::one()).checked_mul(d)
+            .and_then(|v| v.checked_add(d_p * (n + U256::one())))
+            .ok_or(AmmError::Overflow)?;
+        d = numerator / denominator;
+
+        if converged(d, previous) {
+            return Ok(d);
+        }
+    }
+    err!(AmmError::CurveNotConverged)
+}
+
+// Balance of the other side that keeps D constant once one side is at `balance`, as in Curve's get_y
+pub fn compute_y(amp: u64, balance: U256, d: U256) -> Result<U256> {
+    require!(!balance.is_zero(), AmmError::ZeroBalance);
+
+    let ann = ann(amp);
+    let n = U256::from(N_COINS);
+    let c = d.checked_mul(d).ok_or(AmmError::Overflow)? / (balance * n);
+    let c = c.checked_mul(d).ok_or(AmmError::Overflow)? / (ann * n);
+    let b = balance + d / ann;
+
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let previous = y;
+        let numerator = y.checked_mul(y)
+            .and_then(|v| v.checked_add(c))
+            .ok_or(AmmError::Overflow)?;
+        let denominator = (y * n + b).checked_sub(d).ok_or(AmmError::Underflow)?;
+        y = numerator / denominator;
+
+        if converged(y, previous) {
+            return Ok(y);
+        }
+    }
+    err!(AmmError::CurveNotConverged)
+}
+
+// Output for a net (after fee) input, rounded down with one unit kept by the pool
+pub fn amount_out(amp: u64, reserve_in: U256, reserve_out: U256, net_in: U256) -> Result<U256> {
+    let d = compute_d(amp, reserve_in, reserve_out)?;
+    let new_out = compute_y(amp, reserve_in.checked_add(net_in).ok_or(AmmError::Overflow)?, d)?;
+    Ok(reserve_out.saturating_sub(new_out).saturating_sub(U256::one()))
+}
+
+// Net (before fee) input needed to take `amount_out`, rounded up with one unit for the pool
+pub fn net_amount_in(amp: u64, reserve_in: U256, reserve_out: U256, amount_out: U256) -> Result<U256> {
+    require!(amount_out < reserve_out, AmmError::InsufficientBalance);
+    let d = compute_d(amp, reserve_in, reserve_out)?;
+    let new_in = compute_y(amp, reserve_out - amount_out, d)?;
+    Ok(new_in.saturating_sub(reserve_in) + U256::one())
+}
+
+// Marginal price of X in Y, Q64.64. With F = Ann(x + y) + D - Ann*D - D^3 / 4xy,
+// dy/dx = (4*Ann*x*y + D^3/x) / (4*Ann*x*y + D^3/y).
+pub fn spot_price(amp: u64, x: U256, y: U256) -> Result<U256> {
+    let d = compute_d(amp, x, y)?;
+    let d3 = d.checked_mul(d).and_then(|v| v.checked_mul(d)).ok_or(AmmError::Overflow)?;
+    let axy = ann(amp).checked_mul(x)
+        .and_then(|v| v.checked_mul(y))
+        .and_then(|v| v.checked_mul(U256::from(4u8)))
+        .ok_or(AmmError::Overflow)?;
+
+    let mut numerator = axy.checked_add(d3 / x).ok_or(AmmError::Overflow)?;
+    let mut denominator = axy.checked_add(d3 / y).ok_or(AmmError::Overflow)?;
+
+    // Keep room for the Q64.64 shift, the ratio barely moves
+    let excess = numerator.bits().saturating_sub(190);
+    numerator >>= excess;
+    denominator >>= excess;
+    require!(!denominator.is_zero(), AmmError::ZeroBalance);
+
+    Ok((numerator << 64) / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curves::Curve;
+    use crate::state::CurveType;
+
+    fn u(value: u64) -> U256 {
+        U256::from(value)
+    }
+
+    fn diff(a: U256, b: U256) -> U256 {
+        if a > b { a - b } else { b - a }
+    }
+
+    #[test]
+    fn balanced_invariant_is_the_sum() {
+        for amp in [MIN_AMP, 100, MAX_AMP] {
+            assert_eq!(compute_d(amp, u(1_000_000), u(1_000_000)).unwrap(), u(2_000_000));
+        }
+    }
+
+    #[test]
+    fn invariant_converges_on_imbalanced_pools() {
+        let (x, y) = (u(1_000_000_000_000), u(1_000_000));
+        for amp in [MIN_AMP, 100, MAX_AMP] {
+            // Between the constant product (2 * sqrt(xy)) and constant sum invariants
+            let d = compute_d(amp, x, y).unwrap();
+            assert!(d > u(2 * 1_000_000_000) && d < x + y);
+        }
+    }
+
+    #[test]
+    fn compute_y_inverts_compute_d() {
+        let (x, y) = (u(3_000_000_000), u(1_000_000_000));
+        for amp in [MIN_AMP, 100, MAX_AMP] {
+            let d = compute_d(amp, x, y).unwrap();
+            assert!(diff(compute_y(amp, x, d).unwrap(), y) <= U256::one());
+            assert!(diff(compute_y(amp, y, d).unwrap(), x) <= U256::one());
+        }
+    }
+
+    #[test]
+    fn exact_in_and_exact_out_round_trip() {
+        let (reserve_in, reserve_out) = (u(5_000_000_000), u(4_000_000_000));
+        for amp in [MIN_AMP, 100, MAX_AMP] {
+            let out = amount_out(amp, reserve_in, reserve_out, u(10_000_000)).unwrap();
+            let net_in = net_amount_in(amp, reserve_in, reserve_out, out).unwrap();
+
+            // Both directions round in the pool's favour by about a unit
+            assert!(net_in <= u(10_000_000));
+            assert!(u(10_000_000) - net_in <= u(3));
+        }
+    }
+
+    #[test]
+    fn rejects_taking_the_whole_reserve() {
+        assert!(net_amount_in(100, u(1_000), u(1_000), u(1_000)).is_err());
+    }
+
+    #[test]
+    fn normalizes_decimals_to_trade_at_par() {
+        // X has 6 decimals, Y has 9, one million of each in the pool
+        let curve = Curve {
+            curve_type: CurveType::StableSwap,
+            fee: 0,
+            amp: 100,
+            weight_x: 5_000,
+            weight_y: 5_000,
+            scale_x: 1_000,
+            scale_y: 1,
+        };
+        let (reserve_x, reserve_y) = (1_000_000_000_000, 1_000_000_000_000_000);
+
+        let quote = curve.swap_exact_in(true, reserve_x, reserve_y, 0, 1_000_000, 0).unwrap();
+        assert!(quote.amount_out < 1_000_000_000 && quote.amount_out > 999_000_000);
+
+        let quote = curve.swap_exact_out(false, reserve_x, reserve_y, 1_000_000).unwrap();
+        assert!(quote.amount_in > 1_000_000_000 && quote.amount_in < 1_001_000_000);
+    }
+}
@@ -0,0 +1,8 @@
+// Kept apart from the anchor prelude, whose Result alias clashes with the macro expansion
+#![allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+
+use uint::construct_uint;
+
+construct_uint! {
+    pub struct U256(4);
+}
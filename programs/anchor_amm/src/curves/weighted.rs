@@ -0,0 +1,277 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::AmmError;
+use super::U256;
+
+// Weights are basis points of the pool value, weight_x + weight_y = 10_000
+pub const MIN_WEIGHT: u16 = 100;
+pub const MAX_WEIGHT: u16 = 9_900;
+
+// Trades and joins are capped to 30% of the reserve as in Balancer, keeping pow in range
+pub const MAX_IN_RATIO: u64 = 3_000;
+pub const MAX_OUT_RATIO: u64 = 3_000;
+
+const WAD: u128 = 1_000_000_000_000_000_000;
+const LN_2: i128 = 693_147_180_559_945_309;
+// pow is accurate to ~1e-17, results are nudged by this margin towards the pool
+const MAX_POW_RELATIVE_ERROR: u128 = 10_000;
+
+fn mul_down(a: u128, b: u128) -> Result<u128> {
+    let product = U256::from(a) * U256::from(b) / U256::from(WAD);
+    require!(product.bits() <= 128, AmmError::Overflow);
+    Ok(product.as_u128())
+}
+
+fn mul_up(a: u128, b: u128) -> Result<u128> {
+    let product = U256::from(a) * U256::from(b);
+    let mut result = product / U256::from(WAD);
+    if !(product % U256::from(WAD)).is_zero() {
+        result += U256::one();
+    }
+    require!(result.bits() <= 128, AmmError::Overflow);
+    Ok(result.as_u128())
+}
+
+fn div_down(a: u128, b: u128) -> Result<u128> {
+    require!(b > 0, AmmError::ZeroBalance);
+    let quotient = U256::from(a) * U256::from(WAD) / U256::from(b);
+    require!(quotient.bits() <= 128, AmmError::Overflow);
+    Ok(quotient.as_u128())
+}
+
+fn div_up(a: u128, b: u128) -> Result<u128> {
+    require!(b > 0, AmmError::ZeroBalance);
+    let numerator = U256::from(a) * U256::from(WAD);
+    let mut quotient = numerator / U256::from(b);
+    if !(numerator % U256::from(b)).is_zero() {
+        quotient += U256::one();
+    }
+    require!(quotient.bits() <= 128, AmmError::Overflow);
+    Ok(quotient.as_u128())
+}
+
+// Natural log of a positive WAD value. x = m * 2^n with m in [1, 2), then
+// ln(m) = 2 * atanh((m - 1) / (m + 1)) whose series converges quickly for m < 2.
+fn ln(x: u128) -> Result<i128> {
+    require!(x > 0, AmmError::ZeroBalance);
+    let mut m = x;
+    let mut n: i128 = 0;
+    while m >= 2 * WAD {
+        m >>= 1;
+        n += 1;
+    }
+    while m < WAD {
+        m <<= 1;
+        n -= 1;
+    }
+
+    let z = ((m - WAD) * WAD / (m + WAD)) as i128;
+    let z2 = z * z / WAD as i128;
+    let mut term = z;
+    let mut sum: i128 = 0;
+    let mut k: i128 = 1;
+    while term != 0 {
+        sum += term / k;
+        term = term * z2 / WAD as i128;
+        k += 2;
+    }
+    Ok(n * LN_2 + 2 * sum)
+}
+
+// e^x for a signed WAD value. x = k * ln2 + r with |r| <= ln2 / 2, e^r by its Taylor series.
+fn exp(x: i128) -> Result<u128> {
+    let half = if x >= 0 { LN_2 / 2 } else { -LN_2 / 2 };
+    let k = (x + half) / LN_2;
+    let r = x - k * LN_2;
+
+    let mut term = WAD as i128;
+    let mut sum = WAD as i128;
+    let mut i: i128 = 1;
+    while term != 0 {
+        term = term * r / WAD as i128 / i;
+        sum += term;
+        i += 1;
+    }
+
+    let sum = sum as u128;
+    match k {
+        k if k >= 0 => {
+            require!(k < 64, AmmError::Overflow);
+            Ok(sum << k)
+        },
+        k if k > -128 => Ok(sum >> -k),
+        _ => Ok(0),
+    }
+}
+
+// base^exponent for WAD values
+fn pow(base: u128, exponent: u128) -> Result<u128> {
+    if exponent == 0 || base == WAD {
+        return Ok(WAD);
+    }
+    let ln_base = ln(base)?;
+    let magnitude = U256::from(ln_base.unsigned_abs()) * U256::from(exponent) / U256::from(WAD);
+    require!(magnitude.bits() < 127, AmmError::Overflow);
+    let product = magnitude.as_u128() as i128;
+    exp(if ln_base < 0 { -product } else { product })
+}
+
+fn pow_up(base: u128, exponent: u128) -> Result<u128> {
+    let raw = pow(base, exponent)?;
+    Ok(raw + mul_up(raw, MAX_POW_RELATIVE_ERROR)? + 1)
+}
+
+fn pow_down(base: u128, exponent: u128) -> Result<u128> {
+    let raw = pow(base, exponent)?;
+    Ok(raw.saturating_sub(mul_up(raw, MAX_POW_RELATIVE_ERROR)? + 1))
+}
+
+fn to_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| AmmError::Overflow.into())
+}
+
+// out = reserve_out * (1 - (reserve_in / (reserve_in + net_in))^(weight_in / weight_out))
+pub fn amount_out(reserve_in: u64, weight_in: u16, reserve_out: u64, weight_out: u16, net_in: u64) -> Result<u64> {
+    require!(
+        net_in as u128 * 10_000 <= reserve_in as u128 * MAX_IN_RATIO as u128,
+        AmmError::TradeTooLarge
+    );
+    let base = div_up(reserve_in as u128, reserve_in as u128 + net_in as u128)?;
+    let exponent = div_down(weight_in as u128, weight_out as u128)?;
+    let power = pow_up(base, exponent)?;
+    let complement = WAD.saturating_sub(power);
+    to_u64(mul_down(reserve_out as u128, complement)?)
+}
+
+// net_in = reserve_in * ((reserve_out / (reserve_out - amount_out))^(weight_out / weight_in) - 1)
+pub fn net_amount_in(reserve_in: u64, weight_in: u16, reserve_out: u64, weight_out: u16, amount_out: u64) -> Result<u64> {
+    require!(
+        amount_out as u128 * 10_000 <= reserve_out as u128 * MAX_OUT_RATIO as u128,
+        AmmError::TradeTooLarge
+    );
+    let base = div_up(reserve_out as u128, (reserve_out - amount_out) as u128)?;
+    let exponent = div_up(weight_out as u128, weight_in as u128)?;
+    let power = pow_up(base, exponent)?;
+    to_u64(mul_up(reserve_in as u128, power - WAD)?)
+}
+
+// LP minted for a single-sided join of `amount_in`. Only the part of the input that
+// would have to be swapped to keep the weights, (1 - weight_in), pays the swap fee.
+// Returns the LP amount and the fee charged.
+pub fn lp_out_for_single_in(reserve_in: u64, weight_in: u16, lp_supply: u64, amount_in: u64, fee: u16) -> Result<(u64, u64)> {
+    require!(
+        amount_in as u128 * 10_000 <= reserve_in as u128 * MAX_IN_RATIO as u128,
+        AmmError::TradeTooLarge
+    );
+    let taxable = (amount_in as u128 * (10_000 - weight_in) as u128).div_ceil(10_000);
+    let fee_amount = (taxable * fee as u128).div_ceil(10_000);
+    let net_in = amount_in as u128 - fee_amount;
+
+    let ratio = div_down(reserve_in as u128 + net_in, reserve_in as u128)?;
+    let invariant_ratio = pow_down(ratio, weight_in as u128 * WAD / 10_000)?;
+    let growth = invariant_ratio.saturating_sub(WAD);
+
+    Ok((to_u64(mul_down(lp_supply as u128, growth)?)?, to_u64(fee_amount)?))
+}
+
+// Weighted geometric mean reserve_x^weight_x * reserve_y^weight_y, rounded down.
+// Prices the first LP of a pool like sqrt(x * y) does for constant product.
+pub fn invariant(reserve_x: u64, weight_x: u16, reserve_y: u64, weight_y: u16) -> Result<u64> {
+    require!(reserve_x > 0 && reserve_y > 0, AmmError::ZeroBalance);
+    let ln_x = ln(reserve_x as u128 * WAD)?;
+    let ln_y = ln(reserve_y as u128 * WAD)?;
+    let ln_invariant = (ln_x * weight_x as i128 + ln_y * weight_y as i128) / 10_000;
+    let raw = exp(ln_invariant)?;
+    let value = raw.saturating_sub(mul_up(raw, MAX_POW_RELATIVE_ERROR)? + 1);
+    to_u64(value / WAD)
+}
+
+// Q64.64 marginal price of X in Y, (reserve_y / weight_y) / (reserve_x / weight_x)
+pub fn spot_price(reserve_x: u64, weight_x: u16, reserve_y: u64, weight_y: u16) -> Result<u128> {
+    let numerator = U256::from(reserve_y) * U256::from(weight_x);
+    let denominator = U256::from(reserve_x) * U256::from(weight_y);
+    require!(!denominator.is_zero(), AmmError::ZeroBalance);
+    let price = (numerator << 64) / denominator;
+    require!(price.bits() <= 128, AmmError::Overflow);
+    Ok(price.as_u128())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wad(value: f64) -> u128 {
+        (value * WAD as f64) as u128
+    }
+
+    fn float(value: u128) -> f64 {
+        value as f64 / WAD as f64
+    }
+
+    // Tolerance of the fixed point math against f64, whose own error is far smaller
+    const TOLERANCE: f64 = MAX_POW_RELATIVE_ERROR as f64 / WAD as f64;
+
+    #[test]
+    fn pow_stays_within_its_error_bound() {
+        for (base, exponent) in [(0.5, 4.0), (0.9, 0.25), (1.3, 4.0), (2.5, 0.8), (0.7, 9.0), (1.42, 1.0 / 99.0)] {
+            let expected = f64::powf(base, exponent);
+            let raw = float(pow(wad(base), wad(exponent)).unwrap());
+            assert!((raw - expected).abs() / expected < TOLERANCE, "{base}^{exponent}");
+
+            // The nudged results bracket the exact value
+            assert!(float(pow_down(wad(base), wad(exponent)).unwrap()) <= expected);
+            assert!(float(pow_up(wad(base), wad(exponent)).unwrap()) >= expected);
+        }
+    }
+
+    #[test]
+    fn exp_rejects_results_past_u128() {
+        assert!(exp(63 * LN_2).is_ok());
+        assert!(exp(64 * LN_2).is_err());
+        assert!(exp(1_000 * LN_2).is_err());
+        assert_eq!(exp(-200 * LN_2).unwrap(), 0);
+    }
+
+    // Rounded down to whole units, towards the pool
+    #[test]
+    fn prices_80_20_swaps_like_the_closed_form() {
+        // Balanced at a price of 1: 80% of the value in X, 20% in Y
+        let (reserve_x, reserve_y) = (800_000_000u64, 200_000_000u64);
+        let net_in = 1_000_000u64;
+
+        let x_in = amount_out(reserve_x, 8_000, reserve_y, 2_000, net_in).unwrap();
+        let expected = reserve_y as f64 * (1.0 - f64::powf(reserve_x as f64 / (reserve_x + net_in) as f64, 4.0));
+        assert!(x_in as f64 <= expected && x_in as f64 > expected - 1.0);
+
+        let y_in = amount_out(reserve_y, 2_000, reserve_x, 8_000, net_in).unwrap();
+        let expected = reserve_x as f64 * (1.0 - f64::powf(reserve_y as f64 / (reserve_y + net_in) as f64, 0.25));
+        assert!(y_in as f64 <= expected && y_in as f64 > expected - 1.0);
+    }
+
+    #[test]
+    fn exact_out_costs_at_least_the_exact_in_input() {
+        let (reserve_x, reserve_y) = (800_000_000u64, 200_000_000u64);
+        let out = amount_out(reserve_x, 8_000, reserve_y, 2_000, 1_000_000).unwrap();
+        let net_in = net_amount_in(reserve_x, 8_000, reserve_y, 2_000, out).unwrap();
+        assert!((1_000_000..=1_000_001).contains(&net_in));
+
+        let expected = reserve_x as f64 * (f64::powf(reserve_y as f64 / (reserve_y - 250_000) as f64, 0.25) - 1.0);
+        let net_in = net_amount_in(reserve_x, 8_000, reserve_y, 2_000, 250_000).unwrap();
+        assert!(net_in as f64 >= expected && (net_in as f64) < expected + 1.0);
+    }
+
+    #[test]
+    fn rejects_trades_past_the_ratio_caps() {
+        assert!(amount_out(1_000, 5_000, 1_000, 5_000, 301).is_err());
+        assert!(net_amount_in(1_000, 5_000, 1_000, 5_000, 301).is_err());
+    }
+
+    #[test]
+    fn invariant_is_the_weighted_geometric_mean() {
+        assert!((999_999..=1_000_000).contains(&invariant(1_000_000, 5_000, 1_000_000, 5_000).unwrap()));
+
+        let expected = f64::powf(800_000_000.0, 0.8) * f64::powf(200_000_000.0, 0.2);
+        let value = invariant(800_000_000, 8_000, 200_000_000, 2_000).unwrap() as f64;
+        assert!(value <= expected && value > expected - 1.0);
+    }
+}
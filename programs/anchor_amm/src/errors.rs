@@ -3,7 +3,7 @@ use constant_product_curve::CurveError;
 
 #[error_code]
 pub enum AmmError {
-    #[msg("Invalid fee, max is 100")]
+    #[msg("Invalid fee, must be below 10000")]
     InvalidFeeSet,
 
     #[msg("Pool is locked")]
@@ -37,7 +37,127 @@ pub enum AmmError {
     SlippageExceeded,
 
     #[msg("Invalid input mint token")]
-    InvalidInputMint
+    InvalidInputMint,
+
+    #[msg("Invalid protocol fee, max is 10000")]
+    InvalidProtocolFeeSet,
+
+    #[msg("No protocol fees to collect")]
+    NoProtocolFees,
+
+    #[msg("No pending authority")]
+    NoPendingAuthority,
+
+    #[msg("Swaps are paused")]
+    SwapPaused,
+
+    #[msg("Deposits are paused")]
+    DepositPaused,
+
+    #[msg("Withdrawals are paused")]
+    WithdrawPaused,
+
+    #[msg("Invalid pause flags")]
+    InvalidPauseFlags,
+
+    #[msg("Not enough price history for the requested window")]
+    OracleWindowTooLong,
+
+    #[msg("Curve did not converge")]
+    CurveNotConverged,
+
+    #[msg("Invalid amplification coefficient")]
+    InvalidAmp,
+
+    #[msg("Invalid amplification ramp")]
+    InvalidAmpRamp,
+
+    #[msg("Operation not supported by this pool's curve")]
+    UnsupportedCurve,
+
+    #[msg("Invalid pool weight")]
+    InvalidWeight,
+
+    #[msg("Trade too large for the pool")]
+    TradeTooLarge,
+
+    #[msg("Invalid mint extension flags")]
+    InvalidExtensionFlags,
+
+    #[msg("Mint has a freeze authority")]
+    MintFreezeAuthority,
+
+    #[msg("Mint has a permanent delegate")]
+    MintPermanentDelegate,
+
+    #[msg("Mint is non-transferable")]
+    MintNonTransferable,
+
+    #[msg("Mint has a transfer hook")]
+    MintTransferHook,
+
+    #[msg("Pool mints must differ")]
+    IdenticalMints,
+
+    #[msg("mint_x must sort before mint_y")]
+    MintsNotSorted,
+
+    #[msg("Too many fee tiers")]
+    TooManyFeeTiers,
+
+    #[msg("Unknown fee tier")]
+    InvalidFeeTier,
+
+    #[msg("Fee change must be scheduled at least MIN_FEE_DELAY ahead")]
+    FeeDelayTooShort,
+
+    #[msg("Invalid dynamic fee bounds")]
+    InvalidDynamicFee,
+
+    #[msg("Flash loans are paused")]
+    FlashLoanPaused,
+
+    #[msg("A flash loan is in progress")]
+    FlashLoanActive,
+
+    #[msg("No flash loan to repay")]
+    NoFlashLoan,
+
+    #[msg("flash_borrow must be followed by flash_repay in the same transaction")]
+    FlashRepayMissing,
+
+    #[msg("Flash loan not fully repaid")]
+    FlashLoanNotRepaid,
+
+    #[msg("First deposit too small to cover the minimum liquidity")]
+    InsufficientInitialLiquidity,
+
+    #[msg("Vaults hold nothing beyond the reserves")]
+    NothingToSkim,
+
+    #[msg("Unlock time must be later than the current one")]
+    InvalidUnlockTime,
+
+    #[msg("Liquidity is still locked")]
+    LiquidityLocked,
+
+    #[msg("Farm already has the maximum number of rewards")]
+    TooManyRewards,
+
+    #[msg("No such reward on this farm")]
+    InvalidRewardIndex,
+
+    #[msg("Unstaking more than staked")]
+    InsufficientStake,
+
+    #[msg("No rewards to claim")]
+    NoRewards,
+
+    #[msg("Withdrawing more than the rewards nobody is owed")]
+    InsufficientRewards,
+
+    #[msg("Farm already has a reward of this mint")]
+    DuplicateReward
 }
 
 impl From<CurveError> for AmmError {
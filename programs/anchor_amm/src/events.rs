@@ -0,0 +1,302 @@
+use anchor_lang::prelude::*;
+
+use crate::state::CurveType;
+
+#[event]
+pub struct PoolInitialized {
+    pub config: Pubkey,
+    pub index: u64,
+    pub authority: Pubkey,
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+    pub mint_lp: Pubkey,
+    pub seed: u64,
+    pub fee: u16,
+    pub protocol_fee: u16,
+    pub curve_type: CurveType,
+    pub amp: u64,
+    pub weight_x: u16,
+    pub allowed_extensions: u8,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    // Amounts credited to the vaults, after any transfer fee
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub lp_minted: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub lp_supply: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    // Amounts debited from the vaults, before any transfer fee
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub lp_burned: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub lp_supply: u64,
+}
+
+#[event]
+pub struct Swapped {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub mint_in: Pubkey,
+    // Vault-side amounts, net of the input's transfer fee and gross of the output's
+    pub amount_in: u64,
+    pub amount_out: u64,
+    // Total fee charged on the input, protocol_fee is the part set aside from it
+    pub fee: u64,
+    // Fee in basis points the swap was priced with
+    pub fee_rate: u16,
+    pub protocol_fee: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub lp_supply: u64,
+}
+
+#[event]
+pub struct PoolLocked {
+    pub config: Pubkey,
+    pub user: Pubkey,
+}
+
+#[event]
+pub struct PoolUnlocked {
+    pub config: Pubkey,
+    pub user: Pubkey,
+}
+
+#[event]
+pub struct PauseFlagsUpdated {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub paused: u8,
+}
+
+#[event]
+pub struct AmpRampStarted {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub ramp_start_ts: i64,
+    pub ramp_end_ts: i64,
+}
+
+#[event]
+pub struct AmpRampStopped {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub amp: u64,
+}
+
+#[event]
+pub struct PauserUpdated {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub pauser: Option<Pubkey>,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityAccepted {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityRenounced {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct CanonicalPoolRegistered {
+    pub config: Pubkey,
+    pub mint_x: Pubkey,
+    pub mint_y: Pubkey,
+}
+
+#[event]
+pub struct FactoryUpdated {
+    pub factory: Pubkey,
+    pub admin: Pubkey,
+    pub default_fee: u16,
+    pub fee_tiers: Vec<u16>,
+    pub protocol_fee_recipient: Pubkey,
+}
+
+#[event]
+pub struct FeeUpdateScheduled {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub fee: u16,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct FeeUpdated {
+    pub config: Pubkey,
+    pub fee: u16,
+}
+
+#[event]
+pub struct DynamicFeeScheduled {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub enabled: bool,
+    pub min_fee: u16,
+    pub max_fee: u16,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct DynamicFeeUpdated {
+    pub config: Pubkey,
+    pub enabled: bool,
+    pub min_fee: u16,
+    pub max_fee: u16,
+}
+
+#[event]
+pub struct FlashBorrowed {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    // Owed on top of the principal at repayment
+    pub fee: u64,
+}
+
+#[event]
+pub struct FlashLoanRepaid {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub protocol_fee: u64,
+}
+
+#[event]
+pub struct ReservesSynced {
+    pub config: Pubkey,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+}
+
+#[event]
+pub struct ProtocolFeesCollected {
+    pub config: Pubkey,
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+#[event]
+pub struct ReservesSkimmed {
+    pub config: Pubkey,
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+#[event]
+pub struct LiquidityLocked {
+    pub config: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub unlock_at: i64,
+}
+
+#[event]
+pub struct LockExtended {
+    pub config: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub unlock_at: i64,
+}
+
+#[event]
+pub struct LiquidityUnlocked {
+    pub config: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FarmCreated {
+    pub config: Pubkey,
+    pub farm: Pubkey,
+}
+
+#[event]
+pub struct RewardAdded {
+    pub farm: Pubkey,
+    pub mint: Pubkey,
+    pub emissions_per_second: u64,
+}
+
+#[event]
+pub struct RewardFunded {
+    pub farm: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct RewardWithdrawn {
+    pub farm: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct RewardEmissionsUpdated {
+    pub farm: Pubkey,
+    pub mint: Pubkey,
+    pub emissions_per_second: u64,
+}
+
+#[event]
+pub struct LpStaked {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub staked: u64,
+    pub total_staked: u64,
+}
+
+#[event]
+pub struct LpUnstaked {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub staked: u64,
+    pub total_staked: u64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
@@ -10,9 +10,12 @@ macro_rules! assert_non_zero {
 #[macro_export]
 macro_rules! assert_has_authority {
     ($x:expr) => {
-        match $x.config.authority {
+        $crate::assert_has_authority!($x.config, $x.user)
+    };
+    ($config:expr, $user:expr) => {
+        match $config.authority {
             Some(authority) => {
-                require_keys_eq!(authority, $x.user.key)(, AmmError::InvalidAuthority)
+                require_keys_eq!(authority, $user.key(), AmmError::InvalidAuthority)
             },
             None => return err!(AmmError::Unauthorized)
         }
@@ -20,10 +23,18 @@ macro_rules! assert_has_authority {
 }
 
 #[macro_export]
-macro_rules! assert_not_locked {
-    ($x:expr) => {
-        if ($x.config.locked == true) {
+macro_rules! assert_not_paused {
+    ($x:expr, $flag:expr, $error:expr) => {
+        // `lock` pauses everything at once
+        if $x.config.paused == $crate::state::PAUSE_ALL {
             return err!(AmmError::PoolLocked)
         }
+        if $x.config.paused & $flag != 0 {
+            return Err($error.into())
+        }
+        // Vaults are short while a flash loan is out
+        if $x.config.flash_loan.is_some() {
+            return err!(AmmError::FlashLoanActive)
+        }
     };
 }
\ No newline at end of file
@@ -5,7 +5,9 @@ pub use state::*;
 pub mod contexts;
 pub use contexts::*;
 
+pub mod curves;
 pub mod errors;
+pub mod events;
 mod helpers;
 
 declare_id!("2oAPYdwKv92TZr6YELKy4TLXCQxSz16cLzSQ5w7tvFJs");
@@ -14,32 +16,203 @@ declare_id!("2oAPYdwKv92TZr6YELKy4TLXCQxSz16cLzSQ5w7tvFJs");
 pub mod anchor_amm {
     use super::*;
 
-    // Intialize the pool
-    pub fn initialize(ctx: Context<Initialize>, seed: u64, fee:u16) -> Result<()> {
+    // Create the program-wide factory, once
+    pub fn create_factory(ctx: Context<CreateFactory>, default_fee: u16, fee_tiers: Vec<u16>, protocol_fee_recipient: Pubkey) -> Result<()> {
+        ctx.accounts.create_factory(default_fee, fee_tiers, protocol_fee_recipient, &ctx.bumps)
+    }
+
+    // Replace the fee tiers new pools pick from, existing pools keep their fee
+    pub fn set_fee_tiers(ctx: Context<UpdateFactory>, fee_tiers: Vec<u16>) -> Result<()> {
+        ctx.accounts.set_fee_tiers(fee_tiers)
+    }
+
+    pub fn set_default_fee(ctx: Context<UpdateFactory>, default_fee: u16) -> Result<()> {
+        ctx.accounts.set_default_fee(default_fee)
+    }
+
+    pub fn set_protocol_fee_recipient(ctx: Context<UpdateFactory>, protocol_fee_recipient: Pubkey) -> Result<()> {
+        ctx.accounts.set_protocol_fee_recipient(protocol_fee_recipient)
+    }
+
+    pub fn set_factory_admin(ctx: Context<UpdateFactory>, admin: Pubkey) -> Result<()> {
+        ctx.accounts.set_factory_admin(admin)
+    }
+
+    // Intialize the pool with one of the factory's fee tiers, or its default fee without one
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize(ctx: Context<Initialize>, seed: u64, fee_tier: Option<u8>, protocol_fee: u16, curve_type: CurveType, amp: u64, weight_x: u16, allowed_extensions: u8) -> Result<()> {
         // save config
-        ctx.accounts.save_config(seed, fee, &ctx.bumps)
+        ctx.accounts.save_config(seed, fee_tier, protocol_fee, curve_type, amp, weight_x, allowed_extensions, &ctx.bumps)
+    }
+
+    // Register the pool as the main one of its pair, the factory admin can replace it later
+    pub fn register_canonical_pool(ctx: Context<RegisterCanonical>) -> Result<()> {
+        ctx.accounts.register_canonical(&ctx.bumps)
+    }
+
+    // Add liquidity to receive LP tokens. Instructions moving pool tokens take the
+    // TransferHook accounts of hooked mints as remaining accounts
+    pub fn deposit<'info>(ctx: Context<'_, '_, '_, 'info, Deposit<'info>>, amount: u64, max_x:u64, max_y:u64) -> Result<()> {
+        ctx.accounts.deposit(amount, max_x, max_y, ctx.remaining_accounts)
     }
 
-    // Add liquidity to receive LP tokens
-    pub fn deposit(ctx: Context<Deposit>, amount: u64, max_x:u64, max_y:u64) -> Result<()> {
-        ctx.accounts.deposit(amount, max_x, max_y)
+    // Add liquidity with only one of the tokens, the swapped portion pays the pool fee
+    pub fn deposit_single<'info>(ctx: Context<'_, '_, '_, 'info, Deposit<'info>>, mint_in: Pubkey, amount_in: u64, min_lp_out: u64) -> Result<()> {
+        ctx.accounts.deposit_single(mint_in, amount_in, min_lp_out, ctx.remaining_accounts)
     }
 
     // Burn LP tokens to withdraw tokens
-    pub fn withdraw(ctx: Context<Withdraw>, amount:u64, min_x: u64, min_y: u64) -> Result<()> {
-        ctx.accounts.withdraw(amount, min_x, min_y)
+    pub fn withdraw<'info>(ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>, amount:u64, min_x: u64, min_y: u64) -> Result<()> {
+        ctx.accounts.withdraw(amount, min_x, min_y, ctx.remaining_accounts)
+    }
+
+    // Burn LP tokens to receive only mint_out, the other side is swapped with the pool fee
+    pub fn withdraw_single<'info>(ctx: Context<'_, '_, '_, 'info, Withdraw<'info>>, amount: u64, mint_out: Pubkey, min_out: u64) -> Result<()> {
+        ctx.accounts.withdraw_single(amount, mint_out, min_out, ctx.remaining_accounts)
+    }
+
+    pub fn swap<'info>(ctx: Context<'_, '_, '_, 'info, Swap<'info>>, mint_deposit:Pubkey, amount_in: u64, amount_out_min: u64) -> Result<()> {
+        ctx.accounts.swap(mint_deposit, amount_in, amount_out_min, ctx.remaining_accounts)
+    }
+
+    // Receive exactly amount_out of mint_out, paying at most amount_in_max
+    pub fn swap_exact_out<'info>(ctx: Context<'_, '_, '_, 'info, Swap<'info>>, mint_out: Pubkey, amount_out: u64, amount_in_max: u64) -> Result<()> {
+        ctx.accounts.swap_exact_out(mint_out, amount_out, amount_in_max, ctx.remaining_accounts)
+    }
+
+    // Borrow from a vault, a flash_repay for the same pool must follow in the transaction
+    pub fn flash_borrow<'info>(ctx: Context<'_, '_, '_, 'info, Flash<'info>>, mint: Pubkey, amount: u64) -> Result<()> {
+        ctx.accounts.flash_borrow(mint, amount, ctx.remaining_accounts)
+    }
+
+    // Return the borrowed amount plus FLASH_LOAN_FEE and unlock the pool
+    pub fn flash_repay<'info>(ctx: Context<'_, '_, '_, 'info, Flash<'info>>) -> Result<()> {
+        ctx.accounts.flash_repay(ctx.remaining_accounts)
+    }
+
+    // Adopt the vault balances as reserves, counting any direct transfers for LPs
+    pub fn sync(ctx: Context<SyncReserves>) -> Result<()> {
+        ctx.accounts.sync()
+    }
+
+    // Sweep direct transfers to the vaults to the protocol fee recipient's token accounts
+    pub fn skim<'info>(ctx: Context<'_, '_, '_, 'info, Collect<'info>>) -> Result<()> {
+        ctx.accounts.skim(ctx.remaining_accounts)
+    }
+
+    // Escrow LP tokens in position `id` until unlock_at, proving the liquidity stays in the pool
+    pub fn lock_liquidity(ctx: Context<LockLiquidity>, id: u64, amount: u64, unlock_at: i64) -> Result<()> {
+        ctx.accounts.lock_liquidity(id, amount, unlock_at, &ctx.bumps)
+    }
+
+    // Push a position's unlock time further out
+    pub fn extend_lock(ctx: Context<ExtendLock>, unlock_at: i64) -> Result<()> {
+        ctx.accounts.extend_lock(unlock_at)
+    }
+
+    // Return a position's LP once unlocked and close it
+    pub fn unlock_liquidity(ctx: Context<UnlockLiquidity>) -> Result<()> {
+        ctx.accounts.unlock_liquidity()
+    }
+
+    // Set up liquidity mining for the pool's LP token
+    pub fn create_farm(ctx: Context<CreateFarm>) -> Result<()> {
+        ctx.accounts.create_farm(&ctx.bumps)
+    }
+
+    // Add a reward mint emitted to LP stakers, up to MAX_REWARDS per farm
+    pub fn add_reward(ctx: Context<AddReward>, emissions_per_second: u64) -> Result<()> {
+        ctx.accounts.add_reward(emissions_per_second)
+    }
+
+    // Move reward tokens from the authority into the farm, emissions stop when they run out
+    pub fn fund_reward<'info>(ctx: Context<'_, '_, '_, 'info, FundReward<'info>>, index: u8, amount: u64) -> Result<()> {
+        ctx.accounts.fund_reward(index, amount, ctx.remaining_accounts)
+    }
+
+    // Move rewards no staker is owed back to the authority
+    pub fn withdraw_reward<'info>(ctx: Context<'_, '_, '_, 'info, FundReward<'info>>, index: u8, amount: u64) -> Result<()> {
+        ctx.accounts.withdraw_reward(index, amount, ctx.remaining_accounts)
+    }
+
+    pub fn set_reward_emissions(ctx: Context<UpdateFarm>, index: u8, emissions_per_second: u64) -> Result<()> {
+        ctx.accounts.set_reward_emissions(index, emissions_per_second)
     }
 
-    pub fn swap(ctx: Context<Swap>, mint_deposit:Pubkey, amount_in: u64, amount_out_min: u64) -> Result<()> {
-        ctx.accounts.swap(mint_deposit, amount_in, amount_out_min)
+    pub fn stake_lp(ctx: Context<StakeLp>, amount: u64) -> Result<()> {
+        ctx.accounts.stake_lp(amount, &ctx.bumps)
     }
 
+    pub fn unstake_lp(ctx: Context<UnstakeLp>, amount: u64) -> Result<()> {
+        ctx.accounts.unstake_lp(amount)
+    }
+
+    // Pay out the stake's earnings of reward number `index`
+    pub fn claim_rewards<'info>(ctx: Context<'_, '_, '_, 'info, ClaimRewards<'info>>, index: u8) -> Result<()> {
+        ctx.accounts.claim_rewards(index, ctx.remaining_accounts)
+    }
+
+    // Read-only TWAP of both prices over the last `window` seconds, in Q64.64
+    pub fn observe(ctx: Context<Observe>, window: u32) -> Result<Twap> {
+        ctx.accounts.observe(window)
+    }
+
+    // Authority or pauser can lock the pool
     pub fn lock(ctx: Context<Update>) -> Result<()> {
         ctx.accounts.lock()
     }
 
+    // Only the authority can unlock the pool
     pub fn unlock(ctx: Context<Update>) -> Result<()> {
         ctx.accounts.unlock()
     }
+
+    // Pause or resume swap / deposit / withdraw individually, see PAUSE_* flags
+    pub fn set_pause_flags(ctx: Context<Update>, paused: u8) -> Result<()> {
+        ctx.accounts.set_pause_flags(paused)
+    }
+
+    // Move a StableSwap pool's amplification linearly to target_amp by ramp_end_ts
+    pub fn ramp_amp(ctx: Context<Update>, target_amp: u64, ramp_end_ts: i64) -> Result<()> {
+        ctx.accounts.ramp_amp(target_amp, ramp_end_ts)
+    }
+
+    // Freeze the amplification at its current value
+    pub fn stop_ramp_amp(ctx: Context<Update>) -> Result<()> {
+        ctx.accounts.stop_ramp_amp()
+    }
+
+    // Schedule pricing swaps from recent volatility, bounded by two fee tiers
+    pub fn set_dynamic_fee(ctx: Context<SetFee>, enabled: bool, min_fee_tier: u8, max_fee_tier: u8, effective_at: i64) -> Result<()> {
+        ctx.accounts.set_dynamic_fee(enabled, min_fee_tier, max_fee_tier, effective_at)
+    }
+
+    pub fn set_pauser(ctx: Context<Update>, pauser: Option<Pubkey>) -> Result<()> {
+        ctx.accounts.set_pauser(pauser)
+    }
+
+    // Schedule a switch to another fee tier, applied by the first swap after effective_at
+    pub fn set_fee(ctx: Context<SetFee>, fee_tier: u8, effective_at: i64) -> Result<()> {
+        ctx.accounts.set_fee(fee_tier, effective_at)
+    }
+
+    // Send the accrued protocol share of swap fees to the factory's protocol fee recipient
+    pub fn collect_protocol_fees<'info>(ctx: Context<'_, '_, '_, 'info, Collect<'info>>) -> Result<()> {
+        ctx.accounts.collect_protocol_fees(ctx.remaining_accounts)
+    }
+
+    // Nominate a new authority, who has to accept before taking over
+    pub fn propose_authority(ctx: Context<SetAuthority>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.propose_authority(new_authority)
+    }
+
+    pub fn accept_authority(ctx: Context<SetAuthority>) -> Result<()> {
+        ctx.accounts.accept_authority()
+    }
+
+    // Drop the authority for good, making the pool immutable
+    pub fn renounce_authority(ctx: Context<SetAuthority>) -> Result<()> {
+        ctx.accounts.renounce_authority()
+    }
 }
 
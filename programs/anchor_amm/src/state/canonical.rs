@@ -0,0 +1,10 @@
+use anchor_lang::prelude::*;
+
+// The main pool of a mint pair, at [b"canonical", mint_x, mint_y] so routers can find it
+// without knowing its seed. Mints are in canonical order, see Initialize.
+#[account]
+#[derive(InitSpace)]
+pub struct CanonicalPool {
+    pub config: Pubkey,
+    pub bump: u8,
+}
@@ -0,0 +1,203 @@
+use anchor_lang::prelude::*;
+
+use crate::curves::U256;
+use crate::errors::AmmError;
+
+pub const MAX_REWARDS: usize = 4;
+// Scale of acc_reward_per_share
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+// Liquidity mining for one pool's LP token, at [b"farm", config]. Staked LP sits in the
+// farm's associated LP account, rewards in one associated account per reward mint.
+#[account]
+#[derive(InitSpace)]
+pub struct Farm {
+    pub config: Pubkey,
+    pub total_staked: u64,
+    pub last_update_ts: i64,
+    #[max_len(MAX_REWARDS)]
+    pub rewards: Vec<Reward>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct Reward {
+    pub mint: Pubkey,
+    pub emissions_per_second: u64,
+    // Funded but not yet emitted, emissions stop once it runs out
+    pub remaining: u64,
+    // Emitted to stakers but not claimed yet
+    pub owed: u64,
+    // Rewards per staked LP since the reward was added, scaled by REWARD_PRECISION
+    pub acc_reward_per_share: u128,
+}
+
+// One owner's stake in a farm, at [b"stake", farm, owner]
+#[account]
+#[derive(InitSpace)]
+pub struct Stake {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    // amount * acc_reward_per_share at the last settlement, per reward index
+    pub reward_debts: [u128; MAX_REWARDS],
+    // Settled rewards not claimed yet, per reward index
+    pub pending: [u64; MAX_REWARDS],
+    pub bump: u8,
+}
+
+impl Farm {
+    // Emits every reward up to `now` across the current stakers. Nothing is emitted while
+    // nobody stakes, the rewards stay funded for later.
+    pub fn update(&mut self, now: i64) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_update_ts).max(0) as u64;
+        self.last_update_ts = now;
+        if elapsed == 0 || self.total_staked == 0 {
+            return Ok(());
+        }
+        for reward in self.rewards.iter_mut() {
+            let emitted = reward.emissions_per_second.saturating_mul(elapsed).min(reward.remaining);
+            let per_share = (emitted as u128)
+                .checked_mul(REWARD_PRECISION)
+                .ok_or(AmmError::Overflow)?
+                / self.total_staked as u128;
+            // What the per share rounding drops stays funded
+            let distributed = per_share * self.total_staked as u128 / REWARD_PRECISION;
+            let distributed = u64::try_from(distributed).map_err(|_| AmmError::Overflow)?;
+            reward.remaining -= distributed;
+            reward.owed = reward.owed.checked_add(distributed).ok_or(AmmError::Overflow)?;
+            reward.acc_reward_per_share = reward.acc_reward_per_share.checked_add(per_share).ok_or(AmmError::Overflow)?;
+        }
+        Ok(())
+    }
+}
+
+impl Stake {
+    // Moves what the stake earned since its last settlement to `pending`, call after Farm::update
+    pub fn settle(&mut self, farm: &Farm) -> Result<()> {
+        for (i, reward) in farm.rewards.iter().enumerate() {
+            let accrued = self.accrued(reward.acc_reward_per_share)?;
+            let earned = accrued.checked_sub(self.reward_debts[i]).ok_or(AmmError::Underflow)?;
+            self.pending[i] = self.pending[i]
+                .checked_add(u64::try_from(earned).map_err(|_| AmmError::Overflow)?)
+                .ok_or(AmmError::Overflow)?;
+            self.reward_debts[i] = accrued;
+        }
+        Ok(())
+    }
+
+    // Restarts the debts from the current amount, call after the stake changed
+    pub fn reset_debts(&mut self, farm: &Farm) -> Result<()> {
+        for (i, reward) in farm.rewards.iter().enumerate() {
+            self.reward_debts[i] = self.accrued(reward.acc_reward_per_share)?;
+        }
+        Ok(())
+    }
+
+    // Widened as the accumulator can grow huge while little is staked
+    fn accrued(&self, acc_reward_per_share: u128) -> Result<u128> {
+        let accrued = U256::from(self.amount) * U256::from(acc_reward_per_share) / U256::from(REWARD_PRECISION);
+        require!(accrued.bits() <= 128, AmmError::Overflow);
+        Ok(accrued.as_u128())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn farm(total_staked: u64, remaining: u64) -> Farm {
+        Farm {
+            config: Pubkey::default(),
+            total_staked,
+            last_update_ts: 0,
+            rewards: vec![Reward {
+                mint: Pubkey::default(),
+                emissions_per_second: 10,
+                remaining,
+                owed: 0,
+                acc_reward_per_share: 0,
+            }],
+            bump: 0,
+        }
+    }
+
+    fn stake() -> Stake {
+        Stake {
+            farm: Pubkey::default(),
+            owner: Pubkey::default(),
+            amount: 0,
+            reward_debts: [0; MAX_REWARDS],
+            pending: [0; MAX_REWARDS],
+            bump: 0,
+        }
+    }
+
+    // What stake_lp does to the farm and the stake
+    fn deposit(farm: &mut Farm, stake: &mut Stake, amount: u64, now: i64) {
+        farm.update(now).unwrap();
+        stake.settle(farm).unwrap();
+        stake.amount += amount;
+        farm.total_staked += amount;
+        stake.reset_debts(farm).unwrap();
+    }
+
+    fn settle(farm: &mut Farm, stakes: [&mut Stake; 2], now: i64) {
+        farm.update(now).unwrap();
+        for stake in stakes {
+            stake.settle(farm).unwrap();
+        }
+    }
+
+    #[test]
+    fn splits_emissions_by_stake() {
+        let mut farm = farm(0, 1_000);
+        let (mut a, mut b) = (stake(), stake());
+        deposit(&mut farm, &mut a, 400, 0);
+        deposit(&mut farm, &mut b, 100, 10);
+        settle(&mut farm, [&mut a, &mut b], 20);
+
+        // a earns alone for 10 seconds, then 4/5 of the next 10
+        assert_eq!(a.pending[0], 100 + 80);
+        assert_eq!(b.pending[0], 20);
+        assert_eq!(farm.rewards[0].owed, 200);
+    }
+
+    #[test]
+    fn takes_large_stakes_after_a_tiny_one() {
+        let mut farm = farm(0, u64::MAX);
+        farm.rewards[0].emissions_per_second = 1_000_000_000_000;
+        let (mut a, mut b) = (stake(), stake());
+        deposit(&mut farm, &mut a, 1, 0);
+
+        // One base unit takes 1e13 over 10 seconds, pushing the accumulator to 1e25
+        deposit(&mut farm, &mut b, 1_000_000_000_000_000_000, 10);
+        assert_eq!(farm.rewards[0].acc_reward_per_share, 10_000_000_000_000_000_000_000_000);
+
+        settle(&mut farm, [&mut a, &mut b], 11);
+        assert_eq!(a.pending[0], 10_000_000_000_000);
+        // b takes the next second's emission, short of what the per share precision drops
+        assert!((999_999_000_000..=1_000_000_000_000).contains(&b.pending[0]));
+    }
+
+    #[test]
+    fn keeps_rounding_dust_funded() {
+        let mut farm = farm(3, 100);
+        farm.update(1).unwrap();
+
+        // 10 split over 3 LP pays 3 each, the last unit stays for later
+        let reward = farm.rewards[0];
+        assert_eq!(reward.owed, 9);
+        assert_eq!(reward.remaining, 91);
+    }
+
+    #[test]
+    fn owes_no_more_than_funded() {
+        let mut farm = farm(1_000, 25);
+        farm.update(10).unwrap();
+
+        let reward = farm.rewards[0];
+        assert_eq!(reward.owed + reward.remaining, 25);
+        assert_eq!(reward.remaining, 0);
+    }
+}
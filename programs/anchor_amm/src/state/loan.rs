@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+// Fee on flash loans in basis points of the amount borrowed, shared with the protocol like swap fees
+pub const FLASH_LOAN_FEE: u16 = 9;
+
+// An outstanding flash loan, the pool stays locked until it is repaid
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct FlashLoan {
+    pub is_x: bool,
+    pub amount: u64,
+    pub fee: u64,
+    // Vault balance before the loan, it must be back to this plus the fee
+    pub vault_balance: u64,
+}
@@ -1,14 +1,152 @@
 use anchor_lang::prelude::*;
 
+use crate::errors::AmmError;
+
+pub mod oracle;
+pub use oracle::*;
+pub mod volatility;
+pub use volatility::*;
+pub mod loan;
+pub use loan::*;
+pub mod position;
+pub use position::*;
+pub mod farm;
+pub use farm::*;
+pub mod canonical;
+pub use canonical::*;
+pub mod registry;
+pub use registry::*;
+
+pub const PAUSE_SWAP: u8 = 1 << 0;
+pub const PAUSE_DEPOSIT: u8 = 1 << 1;
+pub const PAUSE_WITHDRAW: u8 = 1 << 2;
+pub const PAUSE_FLASH_LOAN: u8 = 1 << 3;
+pub const PAUSE_ALL: u8 = PAUSE_SWAP | PAUSE_DEPOSIT | PAUSE_WITHDRAW | PAUSE_FLASH_LOAN;
+
+// Mint features a pool creator has to opt into, see Config::allowed_extensions
+pub const ALLOW_FREEZE_AUTHORITY: u8 = 1 << 0;
+pub const ALLOW_PERMANENT_DELEGATE: u8 = 1 << 1;
+pub const ALLOW_NON_TRANSFERABLE: u8 = 1 << 2;
+pub const ALLOW_TRANSFER_HOOK: u8 = 1 << 3;
+pub const ALLOW_ALL: u8 = ALLOW_FREEZE_AUTHORITY | ALLOW_PERMANENT_DELEGATE | ALLOW_NON_TRANSFERABLE | ALLOW_TRANSFER_HOOK;
+
+// LP minted to the locked account on the first deposit and never redeemable, so a pool's
+// supply can't be brought down to a few units and inflated by donations
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+// Minimum notice LPs get before a pool's fee changes
+pub const MIN_FEE_DELAY: i64 = 86_400;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum CurveType {
+    ConstantProduct,
+    StableSwap,
+    Weighted,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct Config {
     pub seed: u64,
-    pub authority: Pubkey,
+    // Position in the factory registry, see PoolEntry
+    pub index: u64,
+    // None once the authority has been renounced, the pool is then immutable
+    pub authority: Option<Pubkey>,
+    // Proposed authority waiting to accept the transfer
+    pub pending_authority: Option<Pubkey>,
+    // Can lock the pool but not unlock it, meant for incident response bots
+    pub pauser: Option<Pubkey>,
     pub fee: u16,
+    // Fee scheduled by the authority, replaces `fee` from fee_effective_at
+    pub pending_fee: Option<u16>,
+    pub fee_effective_at: i64,
+    // Dynamic mode prices swaps between min_fee and max_fee from recent volatility,
+    // `fee` is only used in static mode. See state::volatility.
+    pub dynamic_fee: bool,
+    pub min_fee: u16,
+    pub max_fee: u16,
+    // Fee mode scheduled by the authority, applied with pending_fee at fee_effective_at
+    pub pending_dynamic_fee: Option<DynamicFee>,
+    pub volatility: u64,
+    pub volatility_updated_at: i64,
+    // Share of the swap fee kept by the protocol, in basis points of the fee
+    pub protocol_fee: u16,
+    // Protocol fees sitting in the vaults that are not part of the LP reserves
+    pub protocol_fees_x: u64,
+    pub protocol_fees_y: u64,
+    // LP reserves moved only by pool instructions, so direct transfers to the vaults don't
+    // move the price. Donations are reconciled by sync or swept by skim.
+    pub reserve_x: u64,
+    pub reserve_y: u64,
     pub mint_x: Pubkey,
     pub mint_y: Pubkey,
-    pub locked: bool,
+    pub curve_type: CurveType,
+    // StableSwap amplification, ramped linearly from initial_amp to target_amp
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub ramp_start_ts: i64,
+    pub ramp_end_ts: i64,
+    // Basis points of the pool value held in X, 5000 unless the pool is Weighted
+    pub weight_x: u16,
+    // Bitflag set of paused operations, see PAUSE_*
+    pub paused: u8,
+    // Set between flash_borrow and flash_repay, the pool is locked meanwhile
+    pub flash_loan: Option<FlashLoan>,
+    // Bitflag set of risky mint features accepted at creation, see ALLOW_*
+    pub allowed_extensions: u8,
+    // Uniswap v2 style Q64.64 price accumulators, see state::oracle
+    pub price_x_cumulative: u128,
+    pub price_y_cumulative: u128,
+    pub last_update_slot: u64,
+    pub last_update_timestamp: i64,
     pub lp_bump: u8,
     pub bump: u8,
-}
\ No newline at end of file
+}
+
+impl Config {
+    // Vault balances minus the unclaimed protocol fees
+    pub fn vault_reserves(&self, vault_x: u64, vault_y: u64) -> Result<(u64, u64)> {
+        let x = vault_x.checked_sub(self.protocol_fees_x).ok_or(AmmError::Underflow)?;
+        let y = vault_y.checked_sub(self.protocol_fees_y).ok_or(AmmError::Underflow)?;
+        Ok((x, y))
+    }
+
+    // StableSwap amplification at `now`, following the current ramp
+    pub fn amp(&self, now: i64) -> u64 {
+        if now >= self.ramp_end_ts || self.ramp_end_ts <= self.ramp_start_ts {
+            return self.target_amp;
+        }
+        let elapsed = now.saturating_sub(self.ramp_start_ts).max(0) as u128;
+        let duration = (self.ramp_end_ts - self.ramp_start_ts) as u128;
+        match self.target_amp >= self.initial_amp {
+            true => self.initial_amp + ((self.target_amp - self.initial_amp) as u128 * elapsed / duration) as u64,
+            false => self.initial_amp - ((self.initial_amp - self.target_amp) as u128 * elapsed / duration) as u64,
+        }
+    }
+
+    // Switches to the scheduled fee once it is due, returns the new fee if it did
+    pub fn apply_pending_fee(&mut self, now: i64) -> Option<u16> {
+        match self.pending_fee {
+            Some(fee) if now >= self.fee_effective_at => {
+                self.fee = fee;
+                self.pending_fee = None;
+                Some(fee)
+            },
+            _ => None,
+        }
+    }
+
+    // Splits the protocol share off a swap fee and accrues it on the deposited side
+    pub fn accrue_protocol_fee(&mut self, is_x: bool, fee_amount: u64) -> Result<u64> {
+        let protocol_amount = (fee_amount as u128)
+            .checked_mul(self.protocol_fee as u128)
+            .ok_or(AmmError::Overflow)?
+            / 10_000;
+        let accrued = match is_x {
+            true => &mut self.protocol_fees_x,
+            false => &mut self.protocol_fees_y,
+        };
+        *accrued = accrued.checked_add(protocol_amount as u64).ok_or(AmmError::Overflow)?;
+        Ok(protocol_amount as u64)
+    }
+}
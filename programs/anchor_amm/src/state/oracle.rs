@@ -0,0 +1,169 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::AmmError;
+use super::Config;
+
+pub const OBSERVATION_CAPACITY: usize = 64;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct Observation {
+    pub timestamp: i64,
+    pub price_x_cumulative: u128,
+    pub price_y_cumulative: u128,
+}
+
+// Ring buffer of accumulator snapshots, one per second in which the reserves changed
+#[account]
+#[derive(InitSpace)]
+pub struct Oracle {
+    pub config: Pubkey,
+    pub index: u16,
+    pub observations: [Observation; OBSERVATION_CAPACITY],
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct Twap {
+    // Q64.64 price of X in Y and of Y in X
+    pub price_x: u128,
+    pub price_y: u128,
+}
+
+impl Config {
+    // Must run before the reserves change so the elapsed time is priced at the old spot prices.
+    // Accumulators wrap like in Uniswap v2, only differences between snapshots are meaningful.
+    pub fn update_price_accumulators(&mut self, oracle: &mut Oracle, spot_prices: Option<(u128, u128)>, clock: &Clock) {
+        let elapsed = clock.unix_timestamp.saturating_sub(self.last_update_timestamp);
+        if elapsed <= 0 {
+            return;
+        }
+
+        if let Some((price_x, price_y)) = spot_prices {
+            self.price_x_cumulative = self.price_x_cumulative.wrapping_add(price_x.wrapping_mul(elapsed as u128));
+            self.price_y_cumulative = self.price_y_cumulative.wrapping_add(price_y.wrapping_mul(elapsed as u128));
+        }
+        self.last_update_slot = clock.slot;
+        self.last_update_timestamp = clock.unix_timestamp;
+
+        oracle.write(Observation {
+            timestamp: clock.unix_timestamp,
+            price_x_cumulative: self.price_x_cumulative,
+            price_y_cumulative: self.price_y_cumulative,
+        });
+    }
+}
+
+impl Oracle {
+    pub fn write(&mut self, observation: Observation) {
+        self.index = ((self.index as usize + 1) % OBSERVATION_CAPACITY) as u16;
+        self.observations[self.index as usize] = observation;
+    }
+
+    // Time weighted prices over the last `window` seconds. The spot price is constant between
+    // two consecutive observations, so the accumulator at the window start is interpolated exactly.
+    pub fn twap(&self, config: &Config, (spot_x, spot_y): (u128, u128), now: i64, window: u32) -> Result<Twap> {
+        let target = now.checked_sub(window as i64).ok_or(AmmError::Underflow)?;
+
+        // No reserve change inside the window, the spot price held throughout
+        if window == 0 || target >= config.last_update_timestamp {
+            return Ok(Twap { price_x: spot_x, price_y: spot_y });
+        }
+
+        let elapsed = (now - config.last_update_timestamp) as u128;
+        let cumulative_x = config.price_x_cumulative.wrapping_add(spot_x.wrapping_mul(elapsed));
+        let cumulative_y = config.price_y_cumulative.wrapping_add(spot_y.wrapping_mul(elapsed));
+
+        // Walk from oldest to newest for the pair of observations surrounding the target
+        let mut previous: Option<Observation> = None;
+        for i in 1..=OBSERVATION_CAPACITY {
+            let observation = self.observations[(self.index as usize + i) % OBSERVATION_CAPACITY];
+            if observation.timestamp == 0 {
+                continue;
+            }
+            if observation.timestamp > target {
+                let before = previous.ok_or(AmmError::OracleWindowTooLong)?;
+                let span = (observation.timestamp - before.timestamp) as u128;
+                let offset = (target - before.timestamp) as u128;
+
+                let price_x = observation.price_x_cumulative.wrapping_sub(before.price_x_cumulative) / span;
+                let price_y = observation.price_y_cumulative.wrapping_sub(before.price_y_cumulative) / span;
+                let start_x = before.price_x_cumulative.wrapping_add(price_x.wrapping_mul(offset));
+                let start_y = before.price_y_cumulative.wrapping_add(price_y.wrapping_mul(offset));
+
+                return Ok(Twap {
+                    price_x: cumulative_x.wrapping_sub(start_x) / window as u128,
+                    price_y: cumulative_y.wrapping_sub(start_y) / window as u128,
+                });
+            }
+            previous = Some(observation);
+        }
+
+        err!(AmmError::OracleWindowTooLong)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE: u128 = 1 << 64;
+
+    // Pool created at `start`, as left by initialize
+    fn pool(start: i64) -> (Config, Oracle) {
+        let mut config = Config::deserialize(&mut &[0u8; Config::INIT_SPACE][..]).unwrap();
+        let mut oracle = Oracle::deserialize(&mut &[0u8; Oracle::INIT_SPACE][..]).unwrap();
+        config.last_update_timestamp = start;
+        oracle.observations[0].timestamp = start;
+        (config, oracle)
+    }
+
+    // Reserves change at `timestamp`, `price` held since the previous change
+    fn update(config: &mut Config, oracle: &mut Oracle, price: u128, timestamp: i64) {
+        let clock = Clock { unix_timestamp: timestamp, ..Clock::default() };
+        config.update_price_accumulators(oracle, Some((price, price)), &clock);
+    }
+
+    #[test]
+    fn returns_spot_price_without_changes_in_window() {
+        let (mut config, mut oracle) = pool(100);
+        update(&mut config, &mut oracle, 2 * ONE, 110);
+
+        assert_eq!(oracle.twap(&config, (3 * ONE, 3 * ONE), 130, 0).unwrap().price_x, 3 * ONE);
+        assert_eq!(oracle.twap(&config, (3 * ONE, 3 * ONE), 130, 20).unwrap().price_x, 3 * ONE);
+    }
+
+    #[test]
+    fn interpolates_window_start() {
+        let (mut config, mut oracle) = pool(100);
+        update(&mut config, &mut oracle, 2 * ONE, 110);
+        update(&mut config, &mut oracle, ONE, 120);
+
+        // 5s at 2 (from 105), 10s at 1, then 10s at the spot price of 4
+        let twap = oracle.twap(&config, (4 * ONE, 4 * ONE), 130, 25).unwrap();
+        assert_eq!(twap.price_x, 60 * ONE / 25);
+        assert_eq!(twap.price_y, 60 * ONE / 25);
+    }
+
+    #[test]
+    fn skips_updates_within_the_same_second() {
+        let (mut config, mut oracle) = pool(100);
+        update(&mut config, &mut oracle, ONE, 110);
+        update(&mut config, &mut oracle, 5 * ONE, 110);
+
+        assert_eq!(oracle.index, 1);
+        assert_eq!(config.price_x_cumulative, 10 * ONE);
+    }
+
+    #[test]
+    fn overwrites_oldest_observations() {
+        let (mut config, mut oracle) = pool(1);
+        for timestamp in 2..=70 {
+            update(&mut config, &mut oracle, ONE, timestamp);
+        }
+
+        // 69 writes after the seed, only the last 64 (7..=70) are kept
+        assert_eq!(oracle.index as usize, 69 % OBSERVATION_CAPACITY);
+        assert_eq!(oracle.twap(&config, (ONE, ONE), 70, 63).unwrap().price_x, ONE);
+        assert!(oracle.twap(&config, (ONE, ONE), 70, 64).is_err());
+    }
+}
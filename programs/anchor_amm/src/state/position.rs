@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+// LP escrowed until unlock_at, at [b"position", config, owner, id]. The tokens sit in the
+// position's associated LP account so integrators can prove liquidity stays in the pool.
+#[account]
+#[derive(InitSpace)]
+pub struct Position {
+    pub config: Pubkey,
+    pub owner: Pubkey,
+    pub id: u64,
+    pub amount: u64,
+    pub unlock_at: i64,
+    pub bump: u8,
+}
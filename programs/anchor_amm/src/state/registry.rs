@@ -0,0 +1,26 @@
+use anchor_lang::prelude::*;
+
+pub const MAX_FEE_TIERS: usize = 8;
+
+// Program-wide settings and pool registry, a single account at [b"factory"]
+#[account]
+#[derive(InitSpace)]
+pub struct Factory {
+    pub admin: Pubkey,
+    // Number of pools created, also the index of the next one
+    pub pool_count: u64,
+    pub default_fee: u16,
+    // Swap fees pools may be created with, in basis points
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<u16>,
+    pub protocol_fee_recipient: Pubkey,
+    pub bump: u8,
+}
+
+// Points pool number `index` at its config, at [b"pool", index]
+#[account]
+#[derive(InitSpace)]
+pub struct PoolEntry {
+    pub config: Pubkey,
+    pub bump: u8,
+}
@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+
+use super::Config;
+use crate::curves::U256;
+
+// Seconds for the volatility accumulator to decay linearly to zero
+pub const VOLATILITY_DECAY: i64 = 600;
+// Basis points of accumulated price move per basis point of extra fee
+pub const VOLATILITY_PER_FEE_BPS: u64 = 10;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct DynamicFee {
+    pub enabled: bool,
+    pub min_fee: u16,
+    pub max_fee: u16,
+}
+
+impl Config {
+    // Accumulated price move in basis points, decayed to `now`
+    pub fn volatility_at(&self, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.volatility_updated_at).clamp(0, VOLATILITY_DECAY);
+        (self.volatility as u128 * (VOLATILITY_DECAY - elapsed) as u128 / VOLATILITY_DECAY as u128) as u64
+    }
+
+    // Swap fee at `now`. In dynamic mode min_fee plus the recent volatility, capped at max_fee.
+    pub fn swap_fee(&self, now: i64) -> u16 {
+        if !self.dynamic_fee {
+            return self.fee;
+        }
+        let surcharge = self.volatility_at(now) / VOLATILITY_PER_FEE_BPS;
+        (self.min_fee as u64).saturating_add(surcharge).min(self.max_fee as u64) as u16
+    }
+
+    // Switches the fee mode once the scheduled change is due, see apply_pending_fee
+    pub fn apply_pending_dynamic_fee(&mut self, now: i64) -> Option<DynamicFee> {
+        match self.pending_dynamic_fee {
+            Some(dynamic_fee) if now >= self.fee_effective_at => {
+                self.dynamic_fee = dynamic_fee.enabled;
+                self.min_fee = dynamic_fee.min_fee;
+                self.max_fee = dynamic_fee.max_fee;
+                self.pending_dynamic_fee = None;
+                Some(dynamic_fee)
+            },
+            _ => None,
+        }
+    }
+
+    // Adds the move of the X price caused by a swap, tracked in every fee mode
+    pub fn record_price_move(&mut self, price_before: u128, price_after: u128, now: i64) {
+        if price_before == 0 {
+            return;
+        }
+        let change = U256::from(price_before.abs_diff(price_after)) * U256::from(10_000u64) / U256::from(price_before);
+        let change = if change > U256::from(u64::MAX) { u64::MAX } else { change.as_u64() };
+
+        self.volatility = self.volatility_at(now).saturating_add(change);
+        self.volatility_updated_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::deserialize(&mut &[0u8; Config::INIT_SPACE][..]).unwrap();
+        config.fee = 30;
+        config
+    }
+
+    #[test]
+    fn switches_mode_when_due() {
+        let mut config = config();
+        config.pending_dynamic_fee = Some(DynamicFee { enabled: true, min_fee: 5, max_fee: 100 });
+        config.fee_effective_at = 1_000;
+
+        assert!(config.apply_pending_dynamic_fee(999).is_none());
+        assert_eq!(config.swap_fee(999), 30);
+
+        assert!(config.apply_pending_dynamic_fee(1_000).is_some());
+        assert!(config.pending_dynamic_fee.is_none());
+        assert_eq!(config.swap_fee(1_000), 5);
+    }
+
+    #[test]
+    fn charges_for_recent_volatility_within_bounds() {
+        let mut config = config();
+        config.dynamic_fee = true;
+        config.min_fee = 5;
+        config.max_fee = 100;
+
+        // A 2% move adds 20 bps, half of it is left after half the decay period
+        config.record_price_move(10_000, 10_200, 0);
+        assert_eq!(config.swap_fee(0), 25);
+        assert_eq!(config.swap_fee(VOLATILITY_DECAY / 2), 15);
+        assert_eq!(config.swap_fee(VOLATILITY_DECAY), 5);
+
+        config.record_price_move(10_000, 20_000, 0);
+        assert_eq!(config.swap_fee(0), 100);
+    }
+}
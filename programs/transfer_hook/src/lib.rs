@@ -0,0 +1,102 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use spl_tlv_account_resolution::{account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+declare_id!("664iFcV6BUxYiiMDQiFynW7MEH36gC2jQFtsT9TfafMm");
+
+// Minimal transfer hook used by the tests: every transfer of a hooked mint bumps a
+// per-mint counter, which the hook resolves through its ExtraAccountMetaList.
+#[program]
+pub mod transfer_hook {
+    use super::*;
+
+    pub fn initialize_extra_account_meta_list(ctx: Context<InitializeExtraAccountMetaList>) -> Result<()> {
+        ExtraAccountMetaList::init::<ExecuteInstruction>(
+            &mut ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?,
+            &extra_account_metas()?,
+        )?;
+        ctx.accounts.counter.bump = ctx.bumps.counter;
+        Ok(())
+    }
+
+    #[interface(spl_transfer_hook_interface::execute)]
+    pub fn transfer_hook(ctx: Context<TransferHook>, _amount: u64) -> Result<()> {
+        ctx.accounts.counter.transfers += 1;
+        Ok(())
+    }
+}
+
+// The counter PDA of the mint being transferred, the mint is account 1 of Execute
+fn extra_account_metas() -> Result<Vec<ExtraAccountMeta>> {
+    Ok(vec![ExtraAccountMeta::new_with_seeds(
+        &[
+            Seed::Literal { bytes: b"counter".to_vec() },
+            Seed::AccountKey { index: 1 },
+        ],
+        false,
+        true,
+    )?])
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Counter {
+    pub transfers: u64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    #[account(mut)]
+    payer: Signer<'info>,
+
+    /// CHECK: Written as an ExtraAccountMetaList TLV
+    #[account(
+        init,
+        payer = payer,
+        space = ExtraAccountMetaList::size_of(extra_account_metas()?.len())?,
+        seeds = [b"extra-account-metas", mint.key().as_ref()],
+        bump
+    )]
+    extra_account_meta_list: UncheckedAccount<'info>,
+
+    mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Counter::INIT_SPACE,
+        seeds = [b"counter", mint.key().as_ref()],
+        bump
+    )]
+    counter: Account<'info, Counter>,
+
+    system_program: Program<'info, System>,
+}
+
+// Account order is fixed by the transfer hook interface
+#[derive(Accounts)]
+pub struct TransferHook<'info> {
+    #[account(token::mint = mint)]
+    source_token: InterfaceAccount<'info, TokenAccount>,
+
+    mint: InterfaceAccount<'info, Mint>,
+
+    #[account(token::mint = mint)]
+    destination_token: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Owner or delegate of the source, may be a PDA such as a pool config
+    owner: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by its seeds
+    #[account(seeds = [b"extra-account-metas", mint.key().as_ref()], bump)]
+    extra_account_meta_list: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"counter", mint.key().as_ref()],
+        bump = counter.bump,
+    )]
+    counter: Account<'info, Counter>,
+}
@@ -1,16 +1,1715 @@
 import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { PublicKey } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeTransferHookInstruction,
+  createMint,
+  getMintLen,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  transfer,
+} from "@solana/spl-token";
+import { assert } from "chai";
 import { AnchorAmm } from "../target/types/anchor_amm";
+import { TransferHook } from "../target/types/transfer_hook";
 
 describe("anchor_amm", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace.AnchorAmm as Program<AnchorAmm>;
+  const hookProgram = anchor.workspace.TransferHook as Program<TransferHook>;
+  const payer = (provider.wallet as anchor.Wallet).payer;
+  const user = provider.wallet.publicKey;
 
-  it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+  let mintX: PublicKey;
+  let mintY: PublicKey;
+
+  // Every account of the shared `Pool` composite for one pool
+  const poolAccounts = (
+    seed: BN,
+    mints = { mintX, mintY },
+    tokenProgram = TOKEN_PROGRAM_ID
+  ) => {
+    const { mintX, mintY } = mints;
+    const [config] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("amm"),
+        mintX.toBuffer(),
+        mintY.toBuffer(),
+        seed.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [mintLp] = PublicKey.findProgramAddressSync(
+      [Buffer.from("mint"), config.toBuffer()],
+      program.programId
+    );
+    const [oracle] = PublicKey.findProgramAddressSync(
+      [Buffer.from("oracle"), config.toBuffer()],
+      program.programId
+    );
+    return {
+      mintX,
+      mintY,
+      config,
+      vaultX: getAssociatedTokenAddressSync(mintX, config, true, tokenProgram),
+      vaultY: getAssociatedTokenAddressSync(mintY, config, true, tokenProgram),
+      mintLp,
+      oracle,
+      tokenProgram,
+    };
+  };
+
+  // Token account holding a pool's MINIMUM_LIQUIDITY, owned by a PDA the program never signs for
+  const [dead] = PublicKey.findProgramAddressSync([Buffer.from("dead")], program.programId);
+  const lockedLp = (pool: ReturnType<typeof poolAccounts>) =>
+    PublicKey.findProgramAddressSync([Buffer.from("locked"), pool.config.toBuffer()], program.programId)[0];
+  const MINIMUM_LIQUIDITY = 1_000;
+
+  // Config::allowed_extensions flags
+  const ALLOW_FREEZE_AUTHORITY = 1 << 0;
+  const ALLOW_TRANSFER_HOOK = 1 << 3;
+
+  // Pools only accept their mints in canonical (byte) order
+  const sorted = (a: PublicKey, b: PublicKey) =>
+    Buffer.compare(a.toBuffer(), b.toBuffer()) < 0 ? { mintX: a, mintY: b } : { mintX: b, mintY: a };
+
+  const seedA = new BN(1);
+  const seedB = new BN(2);
+  let poolA: ReturnType<typeof poolAccounts>;
+  let poolB: ReturnType<typeof poolAccounts>;
+
+  const [factory] = PublicKey.findProgramAddressSync([Buffer.from("factory")], program.programId);
+  const [programData] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
+
+  // Only the program's upgrade authority, the test wallet, can create the factory
+  const createFactory = (feeTiers: number[], signer?: anchor.web3.Keypair) =>
+    program.methods
+      .createFactory(30, feeTiers, user)
+      .accountsStrict({
+        admin: signer?.publicKey ?? user,
+        program: program.programId,
+        programData,
+        factory,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers(signer ? [signer] : [])
+      .rpc();
+
+  const poolEntry = (index: BN) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), index.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+
+  // Fee tiers of the test factory, pools default to 30 bps
+  const FEE_TIERS = [1, 5, 30, 100];
+
+  const initialize = async (
+    pool: ReturnType<typeof poolAccounts>,
+    seed: BN,
+    allowedExtensions = 0,
+    feeTier: number | null = 2,
+    { protocolFee = 0, curveType = { constantProduct: {} } as any, amp = new BN(0), weightX = 5000 } = {}
+  ) => {
+    const { poolCount } = await program.account.factory.fetch(factory);
+    return program.methods
+      .initialize(seed, feeTier, protocolFee, curveType, amp, weightX, allowedExtensions)
+      .accountsStrict({
+        admin: user,
+        mintX: pool.mintX,
+        mintY: pool.mintY,
+        config: pool.config,
+        factory,
+        poolEntry: poolEntry(poolCount),
+        oracle: pool.oracle,
+        mintLp: pool.mintLp,
+        dead,
+        lockedLp: lockedLp(pool),
+        vaultX: pool.vaultX,
+        vaultY: pool.vaultY,
+        tokenProgram: pool.tokenProgram,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+  };
+
+  const userAtas = (pool: ReturnType<typeof poolAccounts>) => ({
+    userAtaX: getAssociatedTokenAddressSync(pool.mintX, user, false, pool.tokenProgram),
+    userAtaY: getAssociatedTokenAddressSync(pool.mintY, user, false, pool.tokenProgram),
+    userAtaLp: getAssociatedTokenAddressSync(pool.mintLp, user, false, pool.tokenProgram),
+  });
+
+  // Deposit and swap repeat some pool accounts to create the user's token accounts when missing
+  const ataPrograms = (pool: ReturnType<typeof poolAccounts>) => ({
+    tokenProgram: pool.tokenProgram,
+    associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    systemProgram: anchor.web3.SystemProgram.programId,
+  });
+
+  const depositAccounts = (pool: ReturnType<typeof poolAccounts>, atas = userAtas(pool)) => ({
+    user,
+    pool,
+    mintLp: pool.mintLp,
+    ...ataPrograms(pool),
+    ...atas,
+    lockedLp: lockedLp(pool),
+  });
+
+  const swapAccounts = (pool: ReturnType<typeof poolAccounts>, atas = userAtas(pool)) => ({
+    user,
+    pool,
+    mintX: pool.mintX,
+    mintY: pool.mintY,
+    ...ataPrograms(pool),
+    userAtaX: atas.userAtaX,
+    userAtaY: atas.userAtaY,
+  });
+
+  const deposit = (
+    pool: ReturnType<typeof poolAccounts>,
+    atas = userAtas(poolA),
+    max = new BN(1_000_000),
+    remainingAccounts: anchor.web3.AccountMeta[] = [],
+    amount = new BN(1)
+  ) =>
+    program.methods
+      .deposit(amount, max, max)
+      .accountsStrict(depositAccounts(pool, atas))
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+  const swap = (
+    pool: ReturnType<typeof poolAccounts>,
+    mintIn: PublicKey,
+    minOut = new BN(1),
+    atas = userAtas(poolA),
+    remainingAccounts: anchor.web3.AccountMeta[] = []
+  ) =>
+    program.methods
+      .swap(mintIn, new BN(10_000), minOut)
+      .accountsStrict(swapAccounts(pool, atas))
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+  // Token-2022 mint whose transfers call the test hook program
+  const createHookMint = async () => {
+    const mint = anchor.web3.Keypair.generate();
+    const space = getMintLen([ExtensionType.TransferHook]);
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(space);
+    const tx = new anchor.web3.Transaction().add(
+      anchor.web3.SystemProgram.createAccount({
+        fromPubkey: user,
+        newAccountPubkey: mint.publicKey,
+        space,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferHookInstruction(
+        mint.publicKey,
+        user,
+        hookProgram.programId,
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(mint.publicKey, 6, user, null, TOKEN_2022_PROGRAM_ID)
+    );
+    await provider.sendAndConfirm(tx, [mint]);
+    await hookProgram.methods
+      .initializeExtraAccountMetaList()
+      .accounts({ payer: user, mint: mint.publicKey })
+      .rpc();
+    return mint.publicKey;
+  };
+
+  const hookCounter = (mint: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("counter"), mint.toBuffer()],
+      hookProgram.programId
+    )[0];
+
+  // What the pool needs in remaining accounts to transfer a hooked mint
+  const hookAccounts = (mint: PublicKey): anchor.web3.AccountMeta[] => [
+    {
+      pubkey: PublicKey.findProgramAddressSync(
+        [Buffer.from("extra-account-metas"), mint.toBuffer()],
+        hookProgram.programId
+      )[0],
+      isSigner: false,
+      isWritable: false,
+    },
+    { pubkey: hookCounter(mint), isSigner: false, isWritable: true },
+    { pubkey: hookProgram.programId, isSigner: false, isWritable: false },
+  ];
+
+  // Unix time of the cluster's Clock sysvar, what the program compares timestamps against
+  const now = async () => {
+    const clock = await provider.connection.getAccountInfo(anchor.web3.SYSVAR_CLOCK_PUBKEY, "processed");
+    return Number(clock.data.readBigInt64LE(32));
+  };
+
+  // Polls the cluster clock until `timestamp`, failing instead of hanging if it stalls
+  const waitUntil = async (timestamp: number, timeoutMs = 5_000) => {
+    const deadline = Date.now() + timeoutMs;
+    while ((await now()) < timestamp) {
+      assert.isBelow(Date.now(), deadline, `cluster clock did not reach ${timestamp}`);
+      await new Promise((resolve) => setTimeout(resolve, 200));
+    }
+  };
+
+  const balance = async (account: PublicKey) =>
+    Number((await provider.connection.getTokenAccountBalance(account)).value.amount);
+
+  // Token-2022 mint charging `bps` on every transfer
+  const createFeeMint = async (bps: number) => {
+    const mint = anchor.web3.Keypair.generate();
+    const space = getMintLen([ExtensionType.TransferFeeConfig]);
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(space);
+    const tx = new anchor.web3.Transaction().add(
+      anchor.web3.SystemProgram.createAccount({
+        fromPubkey: user,
+        newAccountPubkey: mint.publicKey,
+        space,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        mint.publicKey,
+        user,
+        user,
+        bps,
+        BigInt(1_000_000_000),
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(mint.publicKey, 6, user, null, TOKEN_2022_PROGRAM_ID)
+    );
+    await provider.sendAndConfirm(tx, [mint]);
+    return mint.publicKey;
+  };
+
+  const expectError = async (tx: Promise<string>, code: string) => {
+    try {
+      await tx;
+    } catch (err) {
+      const logs = (err.logs ?? []).join("\n");
+      assert.isTrue(
+        err.error?.errorCode?.code === code || logs.includes(code),
+        `expected ${code}, got ${err}`
+      );
+      return;
+    }
+    assert.fail(`expected ${code}`);
+  };
+
+  // Events the program emitted in a transaction, keyed by name
+  const emitted = async (signature: string) => {
+    await provider.connection.confirmTransaction(signature, "confirmed");
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const events: Record<string, any> = {};
+    for (const event of parser.parseLogs(tx.meta.logMessages)) {
+      events[event.name] = event.data;
+    }
+    return events;
+  };
+
+  before(async () => {
+    await createFactory(FEE_TIERS);
+
+    ({ mintX, mintY } = sorted(
+      await createMint(provider.connection, payer, user, null, 6),
+      await createMint(provider.connection, payer, user, null, 6)
+    ));
+    poolA = poolAccounts(seedA);
+    poolB = poolAccounts(seedB);
+
+    await initialize(poolA, seedA);
+    await initialize(poolB, seedB);
+
+    for (const mint of [mintX, mintY]) {
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, user);
+      await mintTo(provider.connection, payer, mint, ata.address, payer, 100_000_000);
+    }
+    for (const pool of [poolA, poolB]) {
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+    }
+  });
+
+  it("deposits and swaps both ways through the shared pool accounts", async () => {
+    await deposit(poolA);
+    // sqrt(1_000_000 * 1_000_000) LP, less the locked minimum
+    assert.equal(await balance(userAtas(poolA).userAtaLp), 1_000_000 - MINIMUM_LIQUIDITY);
+    assert.equal(await balance(lockedLp(poolA)), MINIMUM_LIQUIDITY);
+
+    await swap(poolA, mintX);
+    await swap(poolA, mintY);
+
+    const vaultY = await provider.connection.getTokenAccountBalance(poolA.vaultY);
+    assert.notEqual(vaultY.value.amount, "1000000");
+  });
+
+  it("rejects the X vault passed as vault_y", async () => {
+    await expectError(deposit({ ...poolA, vaultY: poolA.vaultX }), "ConstraintAssociated");
+    await expectError(swap({ ...poolA, vaultY: poolA.vaultX }, mintY), "ConstraintAssociated");
+  });
+
+  it("rejects a mint that does not belong to the config", async () => {
+    const other = await createMint(provider.connection, payer, user, null, 6);
+    await expectError(deposit({ ...poolA, mintY: other }), "ConstraintHasOne");
+    await expectError(swap({ ...poolA, mintX: mintY, mintY: mintX }, mintX), "ConstraintHasOne");
+  });
+
+  it("rejects another pool's config", async () => {
+    await expectError(deposit({ ...poolA, config: poolB.config }), "ConstraintTokenOwner");
+  });
+
+  it("rejects another pool's LP mint", async () => {
+    await expectError(
+      deposit({ ...poolA, mintLp: poolB.mintLp }, userAtas(poolB)),
+      "ConstraintSeeds"
+    );
+  });
+
+  it("rejects another pool's oracle", async () => {
+    await expectError(swap({ ...poolA, oracle: poolB.oracle }, mintX), "ConstraintSeeds");
+  });
+
+  it("charges the rounded-up input for an exact output", async () => {
+    const { userAtaX, userAtaY } = userAtas(poolA);
+    const swapExactOut = (amountOut: number, amountInMax: number) =>
+      program.methods
+        .swapExactOut(mintY, new BN(amountOut), new BN(amountInMax))
+        .accountsStrict(swapAccounts(poolA))
+        .rpc();
+
+    // Both steps round up: reserve_x * out / (reserve_y - out), then grossed up for the 30 bps fee
+    const config = await program.account.config.fetch(poolA.config);
+    const [reserveX, reserveY, out] = [config.reserveX.toNumber(), config.reserveY.toNumber(), 7_777];
+    const netIn = Math.ceil((reserveX * out) / (reserveY - out));
+    const amountIn = Math.ceil((netIn * 10_000) / (10_000 - 30));
+
+    await expectError(swapExactOut(out, amountIn - 1), "SlippageExceeded");
+
+    const [userX, userY] = [await balance(userAtaX), await balance(userAtaY)];
+    await swapExactOut(out, amountIn);
+    assert.equal(await balance(userAtaX), userX - amountIn);
+    assert.equal(await balance(userAtaY), userY + out);
+  });
+
+  it("creates the user's missing token accounts", async () => {
+    const other = anchor.web3.Keypair.generate();
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: user,
+          toPubkey: other.publicKey,
+          lamports: anchor.web3.LAMPORTS_PER_SOL,
+        })
+      )
+    );
+    const ataX = await getOrCreateAssociatedTokenAccount(provider.connection, payer, mintX, other.publicKey);
+    await mintTo(provider.connection, payer, mintX, ataX.address, payer, 100_000);
+    const atas = { ...userAtas(poolA), userAtaX: ataX.address, userAtaY: getAssociatedTokenAddressSync(mintY, other.publicKey) };
+
+    await program.methods
+      .swap(mintX, new BN(10_000), new BN(1))
+      .accountsStrict({ ...swapAccounts(poolA, atas), user: other.publicKey })
+      .signers([other])
+      .rpc();
+    assert.isAbove(await balance(atas.userAtaY), 0);
+  });
+
+  describe("with Token-2022 transfer fees", () => {
+    // 1% on both mints
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+
+    before(async () => {
+      const mints = sorted(await createFeeMint(100), await createFeeMint(100));
+      pool = poolAccounts(seedA, mints, TOKEN_2022_PROGRAM_ID);
+      await initialize(pool, seedA);
+
+      for (const mint of [mints.mintX, mints.mintY]) {
+        const ata = await getOrCreateAssociatedTokenAccount(
+          provider.connection, payer, mint, user, false, undefined, undefined, TOKEN_2022_PROGRAM_ID
+        );
+        await mintTo(
+          provider.connection, payer, mint, ata.address, payer, 100_000_000, [], undefined, TOKEN_2022_PROGRAM_ID
+        );
+      }
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, pool.mintLp, user, false, undefined, undefined, TOKEN_2022_PROGRAM_ID
+      );
+      atas = userAtas(pool);
+    });
+
+    it("seeds the pool with what reaches the vaults", async () => {
+      await deposit(pool, atas, new BN(1_000_000));
+      assert.equal(await balance(pool.vaultX), 990_000);
+      assert.equal(await balance(pool.vaultY), 990_000);
+    });
+
+    it("prices swaps on the amount credited to the vault", async () => {
+      const [vaultX, vaultY] = [await balance(pool.vaultX), await balance(pool.vaultY)];
+      const userY = await balance(atas.userAtaY);
+      await swap(pool, pool.mintX, new BN(1), atas);
+
+      // 10_000 sent, 1% withheld by the mint on the way in and again on the way out
+      assert.equal(await balance(pool.vaultX), vaultX + 9_900);
+      const sent = vaultY - (await balance(pool.vaultY));
+      assert.equal(await balance(atas.userAtaY), userY + sent - Math.ceil(sent / 100));
+    });
+
+    it("checks slippage on what the user receives", async () => {
+      const userY = await balance(atas.userAtaY);
+      await swap(pool, pool.mintX, new BN(1), atas);
+      const received = (await balance(atas.userAtaY)) - userY;
+
+      await expectError(swap(pool, pool.mintX, new BN(received + 1), atas), "SlippageExceeded");
+    });
+
+    it("delivers exactly the requested amount on exact-out swaps", async () => {
+      const userY = await balance(atas.userAtaY);
+      await program.methods
+        .swapExactOut(pool.mintY, new BN(5_000), new BN(10_000))
+        .accountsStrict(swapAccounts(pool, atas))
+        .rpc();
+      assert.equal(await balance(atas.userAtaY), userY + 5_000);
+    });
+  });
+
+  describe("with Token-2022 transfer hooks", () => {
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+    let remainingAccounts: anchor.web3.AccountMeta[];
+
+    const transfers = async (mint: PublicKey) =>
+      (await hookProgram.account.counter.fetch(hookCounter(mint))).transfers.toNumber();
+
+    before(async () => {
+      const mints = sorted(await createHookMint(), await createHookMint());
+      pool = poolAccounts(seedA, mints, TOKEN_2022_PROGRAM_ID);
+      await initialize(pool, seedA, ALLOW_TRANSFER_HOOK);
+
+      for (const mint of [mints.mintX, mints.mintY]) {
+        const ata = await getOrCreateAssociatedTokenAccount(
+          provider.connection, payer, mint, user, false, undefined, undefined, TOKEN_2022_PROGRAM_ID
+        );
+        await mintTo(
+          provider.connection, payer, mint, ata.address, payer, 100_000_000, [], undefined, TOKEN_2022_PROGRAM_ID
+        );
+      }
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection, payer, pool.mintLp, user, false, undefined, undefined, TOKEN_2022_PROGRAM_ID
+      );
+      atas = userAtas(pool);
+      remainingAccounts = [...hookAccounts(mints.mintX), ...hookAccounts(mints.mintY)];
+    });
+
+    it("fails without the hook accounts", async () => {
+      try {
+        await deposit(pool, atas);
+        assert.fail("expected the deposit to fail");
+      } catch (err) {
+        assert.notInclude(`${err}`, "expected the deposit to fail");
+      }
+    });
+
+    it("runs the hook on deposits and swaps", async () => {
+      await deposit(pool, atas, new BN(1_000_000), remainingAccounts);
+      assert.equal(await transfers(pool.mintX), 1);
+      assert.equal(await transfers(pool.mintY), 1);
+
+      await swap(pool, pool.mintX, new BN(1), atas, remainingAccounts);
+      assert.equal(await transfers(pool.mintX), 2);
+      assert.equal(await transfers(pool.mintY), 2);
+    });
+
+    it("runs the hook on withdrawals", async () => {
+      await program.methods
+        .withdraw(new BN(500_000), new BN(1), new BN(1))
+        .accountsStrict({ user, pool, ...atas })
+        .remainingAccounts(remainingAccounts)
+        .rpc();
+      assert.equal(await transfers(pool.mintX), 3);
+      assert.equal(await transfers(pool.mintY), 3);
+    });
+  });
+
+  describe("mint extension checks", () => {
+    it("rejects a mint with a freeze authority unless allowed", async () => {
+      const frozen = sorted(
+        await createMint(provider.connection, payer, user, user, 6),
+        await createMint(provider.connection, payer, user, null, 6)
+      );
+      const pool = poolAccounts(seedA, frozen);
+      await expectError(initialize(pool, seedA), "MintFreezeAuthority");
+      await initialize(pool, seedA, ALLOW_FREEZE_AUTHORITY);
+    });
+
+    it("rejects a mint with a transfer hook unless allowed", async () => {
+      const hooked = sorted(await createHookMint(), await createHookMint());
+      const pool = poolAccounts(seedA, hooked, TOKEN_2022_PROGRAM_ID);
+      await expectError(initialize(pool, seedA), "MintTransferHook");
+      await expectError(initialize(pool, seedA, ALLOW_FREEZE_AUTHORITY), "MintTransferHook");
+    });
+
+    it("rejects unknown flags", async () => {
+      const pool = poolAccounts(new BN(3));
+      await expectError(initialize(pool, new BN(3), 1 << 7), "InvalidExtensionFlags");
+    });
+  });
+
+  describe("canonical pools", () => {
+    const canonical = () =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("canonical"), mintX.toBuffer(), mintY.toBuffer()],
+        program.programId
+      )[0];
+
+    const register = (pool: ReturnType<typeof poolAccounts>, signer?: anchor.web3.Keypair) =>
+      program.methods
+        .registerCanonicalPool()
+        .accountsStrict({
+          admin: signer?.publicKey ?? user,
+          factory,
+          mintX: pool.mintX,
+          mintY: pool.mintY,
+          config: pool.config,
+          canonical: canonical(),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    it("rejects mints out of canonical order", async () => {
+      const pool = poolAccounts(new BN(4), { mintX: mintY, mintY: mintX });
+      await expectError(initialize(pool, new BN(4)), "MintsNotSorted");
+    });
+
+    it("rejects identical mints", async () => {
+      const pool = poolAccounts(new BN(4), { mintX, mintY: mintX });
+      await expectError(initialize(pool, new BN(4)), "IdenticalMints");
+    });
+
+    it("only lets the factory admin register a pool", async () => {
+      await expectError(register(poolA, anchor.web3.Keypair.generate()), "InvalidAuthority");
+    });
+
+    it("lets the factory admin replace the registered pool", async () => {
+      await register(poolA);
+      assert.isTrue((await program.account.canonicalPool.fetch(canonical())).config.equals(poolA.config));
+
+      await register(poolB);
+      assert.isTrue((await program.account.canonicalPool.fetch(canonical())).config.equals(poolB.config));
+    });
+  });
+
+  describe("factory", () => {
+    it("registers every pool under an incrementing index", async () => {
+      const { index } = await program.account.config.fetch(poolB.config);
+      assert.equal(index.toNumber(), 1);
+
+      const entry = await program.account.poolEntry.fetch(poolEntry(index));
+      assert.isTrue(entry.config.equals(poolB.config));
+
+      const { poolCount } = await program.account.factory.fetch(factory);
+      const last = await program.account.poolEntry.fetch(poolEntry(poolCount.subn(1)));
+      const config = await program.account.config.fetch(last.config);
+      assert.isTrue(config.index.eq(poolCount.subn(1)));
+    });
+
+    it("can only be created by the upgrade authority", async () => {
+      await expectError(createFactory([30], anchor.web3.Keypair.generate()), "InvalidAuthority");
+    });
+
+    it("can only be created once", async () => {
+      try {
+        await createFactory([30]);
+        assert.fail("expected the second factory to fail");
+      } catch (err) {
+        assert.include(`${err}`, "already in use");
+      }
+    });
+
+    it("only lets the admin update it", async () => {
+      const other = anchor.web3.Keypair.generate();
+      await expectError(
+        program.methods
+          .setDefaultFee(5)
+          .accountsStrict({ admin: other.publicKey, factory })
+          .signers([other])
+          .rpc(),
+        "InvalidAuthority"
+      );
+
+      await program.methods.setDefaultFee(5).accountsStrict({ admin: user, factory }).rpc();
+      assert.equal((await program.account.factory.fetch(factory)).defaultFee, 5);
+      await program.methods.setDefaultFee(30).accountsStrict({ admin: user, factory }).rpc();
+    });
+  
+    it("creates pools with the fee of the chosen tier", async () => {
+      assert.equal((await program.account.config.fetch(poolA.config)).fee, 30);
+
+      const pool = poolAccounts(new BN(5));
+      await initialize(pool, new BN(5), 0, 3);
+      assert.equal((await program.account.config.fetch(pool.config)).fee, 100);
+    });
+
+    it("creates pools with the default fee without a tier", async () => {
+      await program.methods.setDefaultFee(100).accountsStrict({ admin: user, factory }).rpc();
+      const pool = poolAccounts(new BN(17));
+      await initialize(pool, new BN(17), 0, null);
+      assert.equal((await program.account.config.fetch(pool.config)).fee, 100);
+      await program.methods.setDefaultFee(30).accountsStrict({ admin: user, factory }).rpc();
+    });
+
+    it("rejects an unknown fee tier", async () => {
+      const pool = poolAccounts(new BN(6));
+      await expectError(initialize(pool, new BN(6), 0, FEE_TIERS.length), "InvalidFeeTier");
+    });
+
+    it("lets the admin replace the fee tiers", async () => {
+      await expectError(
+        program.methods.setFeeTiers([10_000]).accountsStrict({ admin: user, factory }).rpc(),
+        "InvalidFeeSet"
+      );
+      await program.methods.setFeeTiers([...FEE_TIERS, 50]).accountsStrict({ admin: user, factory }).rpc();
+
+      const pool = poolAccounts(new BN(6));
+      await initialize(pool, new BN(6), 0, FEE_TIERS.length);
+      assert.equal((await program.account.config.fetch(pool.config)).fee, 50);
+    });
+  });
+
+  describe("fee updates", () => {
+    const setFee = (feeTier: number, effectiveAt: number, signer?: anchor.web3.Keypair) =>
+      program.methods
+        .setFee(feeTier, new BN(effectiveAt))
+        .accountsStrict({ user: signer?.publicKey ?? user, config: poolA.config, factory })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    it("requires at least a day of notice", async () => {
+      await expectError(setFee(3, (await now()) + 60), "FeeDelayTooShort");
+    });
+
+    it("only lets the authority schedule a fee", async () => {
+      const other = anchor.web3.Keypair.generate();
+      await expectError(setFee(3, (await now()) + 2 * 86_400, other), "InvalidAuthority");
+    });
+
+    it("keeps the current fee until the change is due", async () => {
+      await setFee(3, (await now()) + 2 * 86_400);
+      await swap(poolA, mintX);
+
+      const config = await program.account.config.fetch(poolA.config);
+      assert.equal(config.fee, 30);
+      assert.equal(config.pendingFee, 100);
+    });
+  });
+
+  describe("dynamic fees", () => {
+    const seed = new BN(7);
+    let pool: ReturnType<typeof poolAccounts>;
+
+    // Tiers 1 and 3 of FEE_TIERS, 5 and 100 bps
+    const setDynamicFee = (minTier: number, maxTier: number, effectiveAt: number, signer?: anchor.web3.Keypair) =>
+      program.methods
+        .setDynamicFee(true, minTier, maxTier, new BN(effectiveAt))
+        .accountsStrict({ user: signer?.publicKey ?? user, config: pool.config, factory })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      await deposit(pool, userAtas(pool));
+    });
+
+    it("rejects inverted bounds and unknown tiers", async () => {
+      const effectiveAt = (await now()) + 86_400 + 60;
+      await expectError(setDynamicFee(3, 1, effectiveAt), "InvalidDynamicFee");
+      await expectError(setDynamicFee(1, FEE_TIERS.length, effectiveAt), "InvalidFeeTier");
+    });
+
+    it("requires the authority and a day of notice", async () => {
+      const effectiveAt = (await now()) + 86_400 + 60;
+      await expectError(setDynamicFee(1, 3, effectiveAt, anchor.web3.Keypair.generate()), "InvalidAuthority");
+      await expectError(setDynamicFee(1, 3, effectiveAt - 120), "FeeDelayTooShort");
+    });
+
+    it("keeps the static fee until the switch is due", async () => {
+      const effectiveAt = (await now()) + 86_400 + 60;
+      await setDynamicFee(1, 3, effectiveAt);
+
+      const config = await program.account.config.fetch(pool.config);
+      assert.deepEqual(config.pendingDynamicFee, { enabled: true, minFee: 5, maxFee: 100 });
+      assert.equal(config.feeEffectiveAt.toNumber(), effectiveAt);
+
+      await swap(pool, mintX, new BN(1), userAtas(pool));
+      const swapped = await program.account.config.fetch(pool.config);
+      assert.isFalse(swapped.dynamicFee);
+      assert.isNotNull(swapped.pendingDynamicFee);
+      assert.equal(swapped.fee, 30);
+    });
+  });
+
+  describe("flash loans", () => {
+    const amount = new BN(100_000);
+    const flashAccounts = () => ({
+      user,
+      pool: poolA,
+      userAtaX: userAtas(poolA).userAtaX,
+      userAtaY: userAtas(poolA).userAtaY,
+      instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+    });
+    const borrow = () => program.methods.flashBorrow(mintX, amount).accountsStrict(flashAccounts()).instruction();
+    const repay = () => program.methods.flashRepay().accountsStrict(flashAccounts()).instruction();
+    const send = async (...ixs: anchor.web3.TransactionInstruction[]) =>
+      provider.sendAndConfirm(new anchor.web3.Transaction().add(...ixs));
+
+    it("lends and takes the fee back in the same transaction", async () => {
+      const before = await balance(poolA.vaultX);
+      const { lastUpdateTimestamp, priceXCumulative } = await program.account.config.fetch(poolA.config);
+      await waitUntil(lastUpdateTimestamp.toNumber() + 1);
+      await send(await borrow(), await repay());
+
+      // 9 bps of 100_000, rounded up
+      assert.equal(await balance(poolA.vaultX), before + 90);
+      const config = await program.account.config.fetch(poolA.config);
+      assert.isNull(config.flashLoan);
+
+      // The fee moves the reserves, the price up to then is recorded first
+      assert.isTrue(config.lastUpdateTimestamp.gt(lastUpdateTimestamp));
+      assert.isTrue(config.priceXCumulative.gt(priceXCumulative));
+    });
+
+    it("rejects a borrow without a repay", async () => {
+      await expectError(send(await borrow()), "FlashRepayMissing");
+    });
+
+    it("locks the pool until the loan is repaid", async () => {
+      const swapIx = await program.methods
+        .swap(mintX, new BN(10_000), new BN(1))
+        .accountsStrict(swapAccounts(poolA))
+        .instruction();
+      await expectError(send(await borrow(), swapIx, await repay()), "FlashLoanActive");
+    });
+  });
+
+  describe("single-sided liquidity", () => {
+    const depositSingle = (minLpOut: BN) =>
+      program.methods
+        .depositSingle(mintX, new BN(10_000), minLpOut)
+        .accountsStrict(depositAccounts(poolA))
+        .rpc();
+
+    it("zaps into a constant product pool with one token", async () => {
+      const { userAtaLp, userAtaY } = userAtas(poolA);
+      const [lpBefore, yBefore] = [await balance(userAtaLp), await balance(userAtaY)];
+      await depositSingle(new BN(1));
+
+      assert.isAbove(await balance(userAtaLp), lpBefore);
+      assert.equal(await balance(userAtaY), yBefore);
+    });
+
+    it("enforces the minimum LP out", async () => {
+      await expectError(depositSingle(new BN(1_000_000_000)), "SlippageExceeded");
+    });
+
+    const withdrawSingle = (minOut: BN) =>
+      program.methods
+        .withdrawSingle(new BN(5_000), mintY, minOut)
+        .accountsStrict({ user, pool: poolA, ...userAtas(poolA) })
+        .rpc();
+
+    it("zaps out of a pool into one token", async () => {
+      const { userAtaX, userAtaY } = userAtas(poolA);
+      const [xBefore, yBefore] = [await balance(userAtaX), await balance(userAtaY)];
+      await withdrawSingle(new BN(1));
+
+      assert.equal(await balance(userAtaX), xBefore);
+      assert.isAbove(await balance(userAtaY), yBefore);
+    });
+
+    it("enforces the minimum out on zap outs", async () => {
+      await expectError(withdrawSingle(new BN(1_000_000)), "SlippageExceeded");
+    });
+  });
+
+  describe("minimum liquidity", () => {
+    const seed = new BN(8);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+
+    const withdraw = (amount: number) =>
+      program.methods
+        .withdraw(new BN(amount), new BN(1), new BN(1))
+        .accountsStrict({ user, pool, ...atas })
+        .rpc();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+    });
+
+    it("rejects a first deposit below the minimum liquidity", async () => {
+      await expectError(deposit(pool, atas, new BN(MINIMUM_LIQUIDITY)), "InsufficientInitialLiquidity");
+    });
+
+    it("keeps a donation from stealing the second deposit", async () => {
+      // The attacker seeds the pool with the smallest deposit and donates to the vaults
+      await deposit(pool, atas, new BN(MINIMUM_LIQUIDITY + 1));
+      assert.equal(await balance(atas.userAtaLp), 1);
+      const donation = 1_000_000;
+      await transfer(provider.connection, payer, atas.userAtaX, pool.vaultX, payer, donation);
+      await transfer(provider.connection, payer, atas.userAtaY, pool.vaultY, payer, donation);
+
+      // The victim buys LP at the inflated price and redeems it straight away
+      const [x, y] = [await balance(atas.userAtaX), await balance(atas.userAtaY)];
+      await deposit(pool, atas, new BN(10_000_000), [], new BN(MINIMUM_LIQUIDITY));
+      await withdraw(MINIMUM_LIQUIDITY);
+      assert.isAtMost(x - (await balance(atas.userAtaX)), 2);
+      assert.isAtMost(y - (await balance(atas.userAtaY)), 2);
+
+      // The donation is not part of the reserves, the attacker can't take it back through LP
+      const vaultX = await balance(pool.vaultX);
+      await withdraw(1);
+      assert.isBelow(vaultX - (await balance(pool.vaultX)), donation / 100);
+    });
+  });
+
+  describe("reserve tracking", () => {
+    const seed = new BN(9);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+    const donation = 50_000;
+
+    const reserves = async () => {
+      const config = await program.account.config.fetch(pool.config);
+      return [config.reserveX.toNumber(), config.reserveY.toNumber()];
+    };
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+      await deposit(pool, atas);
+    });
+
+    it("ignores tokens sent straight to the vaults", async () => {
+      const before = await reserves();
+      await transfer(provider.connection, payer, atas.userAtaX, pool.vaultX, payer, donation);
+      assert.deepEqual(await reserves(), before);
+    });
+
+    it("sweeps donations with skim", async () => {
+      const userX = await balance(atas.userAtaX);
+      await program.methods
+        .skim()
+        .accountsStrict({ user, pool, factory, treasuryX: atas.userAtaX, treasuryY: atas.userAtaY })
+        .rpc();
+      assert.equal(await balance(atas.userAtaX), userX + donation);
+      assert.equal(await balance(pool.vaultX), (await reserves())[0]);
+    });
+
+    it("adopts donations with sync", async () => {
+      await transfer(provider.connection, payer, atas.userAtaY, pool.vaultY, payer, donation);
+      const [, reserveY] = await reserves();
+      const { lastUpdateTimestamp } = await program.account.config.fetch(pool.config);
+      await waitUntil(lastUpdateTimestamp.toNumber() + 1);
+      await program.methods.sync().accountsStrict({ user, pool }).rpc();
+      assert.equal((await reserves())[1], reserveY + donation);
+
+      // The price before the donation is recorded up to the sync
+      const config = await program.account.config.fetch(pool.config);
+      assert.isTrue(config.lastUpdateTimestamp.gt(lastUpdateTimestamp));
+    });
+
+    it("only lets the authority sync", async () => {
+      const other = anchor.web3.Keypair.generate();
+      await expectError(
+        program.methods.sync().accountsStrict({ user: other.publicKey, pool }).signers([other]).rpc(),
+        "InvalidAuthority"
+      );
+    });
+  });
+
+  describe("locked positions", () => {
+    const position = (id: BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("position"), poolA.config.toBuffer(), user.toBuffer(), id.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+    const positionLp = (id: BN) => getAssociatedTokenAddressSync(poolA.mintLp, position(id), true);
+
+    const lock = (id: BN, unlockAt: number) =>
+      program.methods
+        .lockLiquidity(id, new BN(1_000), new BN(unlockAt))
+        .accountsStrict({
+          owner: user,
+          config: poolA.config,
+          mintLp: poolA.mintLp,
+          position: position(id),
+          positionLp: positionLp(id),
+          userAtaLp: userAtas(poolA).userAtaLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    const extend = (id: BN, unlockAt: number) =>
+      program.methods
+        .extendLock(new BN(unlockAt))
+        .accountsStrict({ owner: user, position: position(id) })
+        .rpc();
+
+    const unlock = (id: BN) =>
+      program.methods
+        .unlockLiquidity()
+        .accountsStrict({
+          owner: user,
+          mintLp: poolA.mintLp,
+          position: position(id),
+          positionLp: positionLp(id),
+          userAtaLp: userAtas(poolA).userAtaLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    it("escrows LP until the unlock time", async () => {
+      const id = new BN(0);
+      const unlockAt = (await now()) + 86_400;
+      await lock(id, unlockAt);
+
+      assert.equal(await balance(positionLp(id)), 1_000);
+      const account = await program.account.position.fetch(position(id));
+      assert.equal(account.unlockAt.toNumber(), unlockAt);
+      await expectError(unlock(id), "LiquidityLocked");
+    });
+
+    it("only extends locks", async () => {
+      const id = new BN(0);
+      const { unlockAt } = await program.account.position.fetch(position(id));
+      await expectError(extend(id, unlockAt.toNumber() - 1), "InvalidUnlockTime");
+      await extend(id, unlockAt.toNumber() + 86_400);
+    });
+
+    it("returns the LP once unlocked", async () => {
+      const id = new BN(1);
+      const unlockAt = (await now()) + 1;
+      await lock(id, unlockAt);
+      const lp = await balance(userAtas(poolA).userAtaLp);
+
+      await waitUntil(unlockAt);
+      await unlock(id);
+      assert.equal(await balance(userAtas(poolA).userAtaLp), lp + 1_000);
+      assert.isNull(await program.account.position.fetchNullable(position(id)));
+    });
+
+    it("locks into an LP account created ahead of time", async () => {
+      const id = new BN(2);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, poolA.mintLp, position(id), true);
+      await lock(id, (await now()) + 86_400);
+      assert.equal(await balance(positionLp(id)), 1_000);
+    });
+  });
+
+  describe("farming", () => {
+    let rewardMint: PublicKey;
+    let userReward: PublicKey;
+
+    const farm = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("farm"), poolA.config.toBuffer()], program.programId)[0];
+    const stake = () =>
+      PublicKey.findProgramAddressSync([Buffer.from("stake"), farm().toBuffer(), user.toBuffer()], program.programId)[0];
+    const rewardVault = () => getAssociatedTokenAddressSync(rewardMint, farm(), true);
+
+    const unstakeAccounts = () => ({
+      owner: user,
+      farm: farm(),
+      mintLp: poolA.mintLp,
+      stake: stake(),
+      farmLp: getAssociatedTokenAddressSync(poolA.mintLp, farm(), true),
+      userAtaLp: userAtas(poolA).userAtaLp,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+    const stakeAccounts = () => ({ ...unstakeAccounts(), systemProgram: anchor.web3.SystemProgram.programId });
+
+    const claim = (index = 0) =>
+      program.methods
+        .claimRewards(index)
+        .accountsStrict({
+          owner: user,
+          farm: farm(),
+          stake: stake(),
+          rewardMint,
+          rewardVault: rewardVault(),
+          userRewardAta: userReward,
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    const addReward = (mint: PublicKey) =>
+      program.methods
+        .addReward(new BN(100))
+        .accountsStrict({
+          user,
+          config: poolA.config,
+          farm: farm(),
+          rewardMint: mint,
+          rewardVault: getAssociatedTokenAddressSync(mint, farm(), true),
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+    before(async () => {
+      rewardMint = await createMint(provider.connection, payer, user, null, 6);
+      userReward = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, rewardMint, user)).address;
+      await mintTo(provider.connection, payer, rewardMint, userReward, payer, 1_000_000);
+
+      // The farm's LP account can be created before the farm itself
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, poolA.mintLp, farm(), true);
+      await program.methods
+        .createFarm()
+        .accountsStrict({
+          user,
+          config: poolA.config,
+          mintLp: poolA.mintLp,
+          farm: farm(),
+          farmLp: stakeAccounts().farmLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      await addReward(rewardMint);
+      await program.methods
+        .fundReward(0, new BN(1_000_000))
+        .accountsStrict({
+          user,
+          config: poolA.config,
+          farm: farm(),
+          rewardMint,
+          rewardVault: rewardVault(),
+          userRewardAta: userReward,
+          rewardTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    });
+
+    it("escrows staked LP in the farm", async () => {
+      const lp = await balance(userAtas(poolA).userAtaLp);
+      await program.methods.stakeLp(new BN(10_000)).accountsStrict(stakeAccounts()).rpc();
+
+      assert.equal(await balance(userAtas(poolA).userAtaLp), lp - 10_000);
+      assert.equal((await program.account.farm.fetch(farm())).totalStaked.toNumber(), 10_000);
+    });
+
+    it("pays rewards emitted while staked", async () => {
+      await waitUntil((await now()) + 2);
+      const before = await balance(userReward);
+      await claim();
+
+      // The only staker earns the whole emission of 100 per second
+      const earned = (await balance(userReward)) - before;
+      assert.isAtLeast(earned, 100);
+      assert.isAtMost(earned, 1_000);
+    });
+
+    it("rejects an unknown reward", async () => {
+      await expectError(claim(1), "InvalidRewardIndex");
+    });
+
+    it("returns staked LP and keeps earnings claimable", async () => {
+      await waitUntil((await now()) + 1);
+      await program.methods.unstakeLp(new BN(10_000)).accountsStrict(unstakeAccounts()).rpc();
+      assert.equal((await program.account.stake.fetch(stake())).amount.toNumber(), 0);
+
+      const before = await balance(userReward);
+      await claim();
+      assert.isAbove(await balance(userReward), before);
+      await expectError(program.methods.unstakeLp(new BN(1)).accountsStrict(unstakeAccounts()).rpc(), "InsufficientStake");
+    });
+
+    it("gives rewards nobody is owed back to the authority", async () => {
+      const withdraw = (amount: number) =>
+        program.methods
+          .withdrawReward(0, new BN(amount))
+          .accountsStrict({
+            user,
+            config: poolA.config,
+            farm: farm(),
+            rewardMint,
+            rewardVault: rewardVault(),
+            userRewardAta: userReward,
+            rewardTokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+
+      // Nothing is staked anymore, so nothing accrues meanwhile
+      const { rewards } = await program.account.farm.fetch(farm());
+      const unallocated = (await balance(rewardVault())) - rewards[0].owed.toNumber();
+      await expectError(withdraw(unallocated + 1), "InsufficientRewards");
+
+      const before = await balance(userReward);
+      await withdraw(unallocated);
+      assert.equal(await balance(userReward), before + unallocated);
+      assert.equal((await program.account.farm.fetch(farm())).rewards[0].remaining.toNumber(), 0);
+    });
+
+    it("adds a reward whose vault was created ahead of time", async () => {
+      const mint = await createMint(provider.connection, payer, user, null, 6);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, farm(), true);
+      await addReward(mint);
+
+      const { rewards } = await program.account.farm.fetch(farm());
+      assert.equal(rewards[1].mint.toBase58(), mint.toBase58());
+      await expectError(addReward(mint), "DuplicateReward");
+    });
+  });
+
+  describe("protocol fees", () => {
+    const seed = new BN(10);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+
+    const collect = (signer?: anchor.web3.Keypair, treasuryX = atas.userAtaX) =>
+      program.methods
+        .collectProtocolFees()
+        .accountsStrict({ user: signer?.publicKey ?? user, pool, factory, treasuryX, treasuryY: atas.userAtaY })
+        .signers(signer ? [signer] : [])
+        .rpc();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      // Half of every swap fee goes to the protocol
+      await initialize(pool, seed, 0, 2, { protocolFee: 5_000 });
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+      await deposit(pool, atas);
+    });
+
+    it("sets the protocol share of swap fees aside", async () => {
+      await swap(pool, mintX, new BN(1), atas);
+
+      // 30 bps of 10_000 in, half of it for the protocol
+      const config = await program.account.config.fetch(pool.config);
+      assert.equal(config.protocolFeesX.toNumber(), 15);
+      assert.equal(config.protocolFeesY.toNumber(), 0);
+      assert.equal(await balance(pool.vaultX), config.reserveX.toNumber() + 15);
+    });
+
+    it("keeps unclaimed fees out of withdrawals", async () => {
+      await program.methods
+        .withdraw(new BN(Math.floor((await balance(atas.userAtaLp)) / 2)), new BN(1), new BN(1))
+        .accountsStrict({ user, pool, ...atas })
+        .rpc();
+
+      const config = await program.account.config.fetch(pool.config);
+      assert.equal(await balance(pool.vaultX), config.reserveX.toNumber() + config.protocolFeesX.toNumber());
+    });
+
+    it("only pays the factory's protocol fee recipient", async () => {
+      const other = anchor.web3.Keypair.generate();
+      const ata = await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintX, other.publicKey);
+      await expectError(collect(other, ata.address), "ConstraintTokenOwner");
+    });
+
+    it("lets anyone collect the accrued fees without touching the reserves", async () => {
+      // The test wallet is the recipient
+      const userX = await balance(atas.userAtaX);
+      const { reserveX } = await program.account.config.fetch(pool.config);
+      await collect(anchor.web3.Keypair.generate());
+
+      const config = await program.account.config.fetch(pool.config);
+      assert.equal(await balance(atas.userAtaX), userX + 15);
+      assert.equal(config.protocolFeesX.toNumber(), 0);
+      assert.equal(config.reserveX.toNumber(), reserveX.toNumber());
+      await expectError(collect(), "NoProtocolFees");
+    });
+  });
+
+  describe("authority transfer", () => {
+    const seed = new BN(11);
+    let pool: ReturnType<typeof poolAccounts>;
+    const next = anchor.web3.Keypair.generate();
+
+    const propose = (newAuthority: PublicKey, signer?: anchor.web3.Keypair) =>
+      program.methods
+        .proposeAuthority(newAuthority)
+        .accountsStrict({ user: signer?.publicKey ?? user, config: pool.config })
+        .signers(signer ? [signer] : [])
+        .rpc();
+    const accept = (signer: anchor.web3.Keypair) =>
+      program.methods
+        .acceptAuthority()
+        .accountsStrict({ user: signer.publicKey, config: pool.config })
+        .signers([signer])
+        .rpc();
+    const renounce = (signer: anchor.web3.Keypair) =>
+      program.methods
+        .renounceAuthority()
+        .accountsStrict({ user: signer.publicKey, config: pool.config })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+    });
+
+    it("needs a proposal to accept", async () => {
+      await expectError(accept(next), "NoPendingAuthority");
+    });
+
+    it("only lets the authority propose", async () => {
+      await expectError(propose(next.publicKey, next), "InvalidAuthority");
+    });
+
+    it("only lets the proposed key accept", async () => {
+      await propose(next.publicKey);
+      await expectError(accept(anchor.web3.Keypair.generate()), "InvalidAuthority");
+
+      await accept(next);
+      const config = await program.account.config.fetch(pool.config);
+      assert.isTrue(config.authority.equals(next.publicKey));
+      assert.isNull(config.pendingAuthority);
+    });
+
+    it("makes the pool immutable on renounce", async () => {
+      const other = anchor.web3.Keypair.generate();
+      await propose(other.publicKey, next);
+      await renounce(next);
+
+      const config = await program.account.config.fetch(pool.config);
+      assert.isNull(config.authority);
+      assert.isNull(config.pendingAuthority);
+      // A proposal made before renouncing can't revive the pool
+      await expectError(accept(other), "NoPendingAuthority");
+      await expectError(renounce(next), "Unauthorized");
+    });
+  });
+
+  describe("pauser", () => {
+    const seed = new BN(13);
+    let pool: ReturnType<typeof poolAccounts>;
+    const pauser = anchor.web3.Keypair.generate();
+
+    const update = (signer?: anchor.web3.Keypair) => ({
+      user: signer?.publicKey ?? user,
+      mintX,
+      mintY,
+      config: pool.config,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    });
+    const lock = (signer?: anchor.web3.Keypair) =>
+      program.methods.lock().accountsStrict(update(signer)).signers(signer ? [signer] : []).rpc();
+    const unlock = (signer?: anchor.web3.Keypair) =>
+      program.methods.unlock().accountsStrict(update(signer)).signers(signer ? [signer] : []).rpc();
+    const renounce = () =>
+      program.methods.renounceAuthority().accountsStrict({ user, config: pool.config }).rpc();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+      await program.methods.setPauser(pauser.publicKey).accountsStrict(update()).rpc();
+    });
+
+    it("can lock but not unlock", async () => {
+      await lock(pauser);
+      await expectError(unlock(pauser), "InvalidAuthority");
+    });
+
+    it("keeps the authority from renouncing while withdrawals are paused", async () => {
+      await expectError(renounce(), "WithdrawPaused");
+      await unlock();
+    });
+
+    it("loses its role when the authority renounces", async () => {
+      await renounce();
+      assert.isNull((await program.account.config.fetch(pool.config)).pauser);
+      await expectError(lock(pauser), "Unauthorized");
+    });
+  });
+
+  describe("pause flags", () => {
+    const seed = new BN(12);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+    const [PAUSE_SWAP, PAUSE_DEPOSIT] = [1 << 0, 1 << 1];
+
+    const update = () => ({
+      user,
+      mintX,
+      mintY,
+      config: pool.config,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    });
+    const setPauseFlags = (paused: number) => program.methods.setPauseFlags(paused).accountsStrict(update()).rpc();
+    const withdraw = () =>
+      program.methods
+        .withdraw(new BN(1_000), new BN(1), new BN(1))
+        .accountsStrict({ user, pool, ...atas })
+        .rpc();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+      await deposit(pool, atas);
+    });
+
+    it("keeps withdrawals open while swaps and deposits are paused", async () => {
+      await setPauseFlags(PAUSE_SWAP | PAUSE_DEPOSIT);
+      await expectError(swap(pool, mintX, new BN(1), atas), "SwapPaused");
+      await expectError(deposit(pool, atas), "DepositPaused");
+      await withdraw();
+    });
+
+    it("reports each paused operation", async () => {
+      await setPauseFlags(PAUSE_SWAP);
+      await expectError(swap(pool, mintX, new BN(1), atas), "SwapPaused");
+      await deposit(pool, atas);
+      // Zapping in swaps part of the input
+      await expectError(
+        program.methods.depositSingle(mintX, new BN(1_000), new BN(1)).accountsStrict(depositAccounts(pool)).rpc(),
+        "SwapPaused"
+      );
+      await setPauseFlags(0);
+      await swap(pool, mintX, new BN(1), atas);
+    });
+
+    it("reports a locked pool", async () => {
+      await program.methods.lock().accountsStrict(update()).rpc();
+      await expectError(withdraw(), "PoolLocked");
+      await expectError(swap(pool, mintX, new BN(1), atas), "PoolLocked");
+      await program.methods.unlock().accountsStrict(update()).rpc();
+      await withdraw();
+    });
+  });
+
+  describe("events", () => {
+    const seed = new BN(18);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+
+    const lpSupply = async () => Number((await provider.connection.getTokenSupply(pool.mintLp)).value.amount);
+    const vaults = async () => [await balance(pool.vaultX), await balance(pool.vaultY)];
+    const reserves = async () => {
+      const { reserveX, reserveY } = await program.account.config.fetch(pool.config);
+      return [reserveX.toNumber(), reserveY.toNumber()];
+    };
+    const update = () => ({
+      user,
+      mintX,
+      mintY,
+      config: pool.config,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    });
+
+    it("describes a new pool", async () => {
+      pool = poolAccounts(seed);
+      const { poolInitialized } = await emitted(await initialize(pool, seed, 0, 2, { protocolFee: 5_000 }));
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+
+      assert.isTrue(poolInitialized.config.equals(pool.config));
+      assert.isTrue(poolInitialized.authority.equals(user));
+      assert.isTrue(poolInitialized.mintX.equals(pool.mintX));
+      assert.isTrue(poolInitialized.mintY.equals(pool.mintY));
+      assert.isTrue(poolInitialized.mintLp.equals(pool.mintLp));
+      assert.equal(poolInitialized.seed.toNumber(), 18);
+      assert.equal(poolInitialized.fee, 30);
+      assert.equal(poolInitialized.protocolFee, 5_000);
+      assert.deepEqual(poolInitialized.curveType, { constantProduct: {} });
+    });
+
+    it("reports the first deposit with the locked minimum liquidity", async () => {
+      const { liquidityAdded } = await emitted(await deposit(pool, atas));
+
+      // sqrt(1_000_000 * 1_000_000) LP, of which MINIMUM_LIQUIDITY stays locked
+      assert.equal(liquidityAdded.amountX.toNumber(), 1_000_000);
+      assert.equal(liquidityAdded.amountY.toNumber(), 1_000_000);
+      assert.equal(liquidityAdded.lpMinted.toNumber(), 1_000_000 - MINIMUM_LIQUIDITY);
+      assert.equal(liquidityAdded.lpSupply.toNumber(), 1_000_000);
+      assert.equal(liquidityAdded.lpSupply.toNumber(), await lpSupply());
+      assert.deepEqual([liquidityAdded.reserveX.toNumber(), liquidityAdded.reserveY.toNumber()], await reserves());
+    });
+
+    it("reports later deposits against the LP supply", async () => {
+      const [x, y] = await vaults();
+      const { liquidityAdded } = await emitted(await deposit(pool, atas, new BN(1_000_000), [], new BN(10_000)));
+      const [xAfter, yAfter] = await vaults();
+
+      assert.equal(liquidityAdded.amountX.toNumber(), xAfter - x);
+      assert.equal(liquidityAdded.amountY.toNumber(), yAfter - y);
+      assert.equal(liquidityAdded.lpMinted.toNumber(), 10_000);
+      assert.equal(liquidityAdded.lpSupply.toNumber(), 1_010_000);
+      assert.equal(liquidityAdded.lpSupply.toNumber(), await lpSupply());
+      assert.deepEqual([liquidityAdded.reserveX.toNumber(), liquidityAdded.reserveY.toNumber()], await reserves());
+    });
+
+    it("reports a swap's fee and the reserves after it", async () => {
+      const [reserveX, reserveY] = await reserves();
+      const y = await balance(atas.userAtaY);
+      const { swapped } = await emitted(await swap(pool, mintX, new BN(1), atas));
+
+      // 30 bps of 10_000 in, half of it set aside for the protocol
+      assert.isTrue(swapped.mintIn.equals(mintX));
+      assert.equal(swapped.amountIn.toNumber(), 10_000);
+      assert.equal(swapped.amountOut.toNumber(), (await balance(atas.userAtaY)) - y);
+      assert.equal(swapped.fee.toNumber(), 30);
+      assert.equal(swapped.feeRate, 30);
+      assert.equal(swapped.protocolFee.toNumber(), 15);
+      assert.equal(swapped.reserveX.toNumber(), reserveX + 10_000 - 15);
+      assert.equal(swapped.reserveY.toNumber(), reserveY - swapped.amountOut.toNumber());
+      assert.deepEqual([swapped.reserveX.toNumber(), swapped.reserveY.toNumber()], await reserves());
+      assert.equal(swapped.lpSupply.toNumber(), 1_010_000);
+    });
+
+    it("reports a withdrawal against the LP supply", async () => {
+      const [x, y] = await vaults();
+      const { liquidityRemoved } = await emitted(
+        await program.methods
+          .withdraw(new BN(100_000), new BN(1), new BN(1))
+          .accountsStrict({ user, pool, ...atas })
+          .rpc()
+      );
+      const [xAfter, yAfter] = await vaults();
+
+      assert.equal(liquidityRemoved.amountX.toNumber(), x - xAfter);
+      assert.equal(liquidityRemoved.amountY.toNumber(), y - yAfter);
+      assert.equal(liquidityRemoved.lpBurned.toNumber(), 100_000);
+      assert.equal(liquidityRemoved.lpSupply.toNumber(), 910_000);
+      assert.equal(liquidityRemoved.lpSupply.toNumber(), await lpSupply());
+      assert.deepEqual([liquidityRemoved.reserveX.toNumber(), liquidityRemoved.reserveY.toNumber()], await reserves());
+    });
+
+    it("reports locking and unlocking the pool", async () => {
+      const { poolLocked } = await emitted(await program.methods.lock().accountsStrict(update()).rpc());
+      assert.isTrue(poolLocked.config.equals(pool.config));
+      assert.isTrue(poolLocked.user.equals(user));
+
+      const { poolUnlocked } = await emitted(await program.methods.unlock().accountsStrict(update()).rpc());
+      assert.isTrue(poolUnlocked.config.equals(pool.config));
+      assert.isTrue(poolUnlocked.user.equals(user));
+    });
+  });
+
+  describe("price oracle", () => {
+    const seed = new BN(14);
+    const ONE = new BN(1).shln(64);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+
+    const observe = (window: number) =>
+      program.methods
+        .observe(window)
+        .accountsStrict({ mintX: pool.mintX, mintY: pool.mintY, config: pool.config, oracle: pool.oracle })
+        .view();
+
+    before(async () => {
+      pool = poolAccounts(seed);
+      await initialize(pool, seed);
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+      await deposit(pool, atas);
+    });
+
+    it("returns the spot price when the reserves did not change in the window", async () => {
+      // Balanced reserves, both prices are 1 in Q64.64
+      const twap = await observe(0);
+      assert.isTrue(twap.priceX.eq(ONE));
+      assert.isTrue(twap.priceY.eq(ONE));
+    });
+
+    it("averages the prices since the window start", async () => {
+      const deposited = (await program.account.config.fetch(pool.config)).lastUpdateTimestamp.toNumber();
+      await waitUntil(deposited + 4);
+      await swap(pool, mintX, new BN(1), atas);
+      const swapped = (await program.account.config.fetch(pool.config)).lastUpdateTimestamp.toNumber();
+      await waitUntil(swapped + 4);
+
+      // The window starts between the deposit and the swap: part at 1, part at the new spot price
+      const twap = await observe((await now()) - swapped + 2);
+      const spot = await observe(0);
+      assert.isTrue(spot.priceX.lt(twap.priceX) && twap.priceX.lt(ONE));
+      assert.isTrue(spot.priceY.gt(twap.priceY) && twap.priceY.gt(ONE));
+    });
+
+    it("writes one observation per second with reserve changes", async () => {
+      const config = await program.account.config.fetch(pool.config);
+      await waitUntil(config.lastUpdateTimestamp.toNumber() + 1);
+      const before = await program.account.oracle.fetch(pool.oracle);
+
+      const ix = () =>
+        program.methods
+          .swap(mintX, new BN(10_000), new BN(1))
+          .accountsStrict(swapAccounts(pool, atas))
+          .instruction();
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(await ix(), await ix()));
+
+      const oracle = await program.account.oracle.fetch(pool.oracle);
+      const updated = await program.account.config.fetch(pool.config);
+      assert.equal(oracle.index, before.index + 1);
+      const latest = oracle.observations[oracle.index];
+      assert.isTrue(latest.timestamp.eq(updated.lastUpdateTimestamp));
+      assert.isTrue(latest.priceXCumulative.eq(updated.priceXCumulative));
+    });
+
+    it("rejects a window older than the oldest observation", async () => {
+      await expectError(observe(1_000_000) as any, "OracleWindowTooLong");
+    });
+  });
+
+  describe("stable pools", () => {
+    const seed = new BN(15);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+    let six: PublicKey;
+    let nine: PublicKey;
+
+    const update = () => ({
+      user,
+      mintX: pool.mintX,
+      mintY: pool.mintY,
+      config: pool.config,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    });
+    const rampAmp = (targetAmp: number, rampEndTs: number) =>
+      program.methods.rampAmp(new BN(targetAmp), new BN(rampEndTs)).accountsStrict(update()).rpc();
+
+    before(async () => {
+      six = await createMint(provider.connection, payer, user, null, 6);
+      nine = await createMint(provider.connection, payer, user, null, 9);
+      pool = poolAccounts(seed, sorted(six, nine));
+      await initialize(pool, seed, 0, 2, { curveType: { stableSwap: {} }, amp: new BN(100) });
+
+      for (const mint of [six, nine]) {
+        const ata = await getOrCreateAssociatedTokenAccount(provider.connection, payer, mint, user);
+        await mintTo(provider.connection, payer, mint, ata.address, payer, 10_000_000_000_000);
+      }
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+
+      // A thousand tokens of each side
+      const [maxX, maxY] = pool.mintX.equals(six) ? [1_000_000_000, 1_000_000_000_000] : [1_000_000_000_000, 1_000_000_000];
+      await program.methods
+        .deposit(new BN(1), new BN(maxX), new BN(maxY))
+        .accountsStrict(depositAccounts(pool, atas))
+        .rpc();
+    });
+
+    it("rejects mints whose decimals are too far apart", async () => {
+      const mints = sorted(
+        await createMint(provider.connection, payer, user, null, 0),
+        await createMint(provider.connection, payer, user, null, 19)
+      );
+      const other = poolAccounts(seed, mints);
+      await expectError(
+        initialize(other, seed, 0, 2, { curveType: { stableSwap: {} }, amp: new BN(100) }),
+        "InvalidPrecision"
+      );
+    });
+
+    it("swaps mints with different decimals close to par", async () => {
+      const out = getAssociatedTokenAddressSync(nine, user);
+      const before = await balance(out);
+      await program.methods
+        .swap(six, new BN(1_000_000), new BN(1))
+        .accountsStrict(swapAccounts(pool, atas))
+        .rpc();
+
+      // One token in, one token out less the 30 bps fee and a little slippage
+      const received = (await balance(out)) - before;
+      assert.isAbove(received, 996_000_000);
+      assert.isBelow(received, 997_000_000);
+    });
+
+    it("only ramps the amplification slowly and within bounds", async () => {
+      const start = await now();
+      await expectError(rampAmp(1_000, start + 3_600), "InvalidAmpRamp");
+      await expectError(rampAmp(1_001, start + 86_500), "InvalidAmpRamp");
+      await expectError(rampAmp(9, start + 86_500), "InvalidAmpRamp");
+
+      await rampAmp(1_000, start + 86_500);
+      let config = await program.account.config.fetch(pool.config);
+      assert.equal(config.initialAmp.toNumber(), 100);
+      assert.equal(config.targetAmp.toNumber(), 1_000);
+      // Another ramp has to wait for this one to end
+      await expectError(rampAmp(200, start + 200_000), "InvalidAmpRamp");
+
+      // Stopping freezes the amplification reached so far, a few seconds in
+      await program.methods.stopRampAmp().accountsStrict(update()).rpc();
+      config = await program.account.config.fetch(pool.config);
+      assert.isTrue(config.targetAmp.eq(config.initialAmp));
+      assert.isBelow(config.targetAmp.toNumber(), 110);
+    });
+  });
+
+  describe("weighted pools", () => {
+    const seed = new BN(16);
+    let pool: ReturnType<typeof poolAccounts>;
+    let atas: ReturnType<typeof userAtas>;
+
+    before(() => {
+      pool = poolAccounts(seed);
+    });
+
+    it("rejects weights outside the bounds", async () => {
+      await expectError(initialize(pool, seed, 0, 2, { curveType: { weighted: {} }, weightX: 50 }), "InvalidWeight");
+    });
+
+    it("prices the first LP from the weighted invariant", async () => {
+      // 80/20, both sides worth the same at a price of 1
+      await initialize(pool, seed, 0, 2, { curveType: { weighted: {} }, weightX: 8_000 });
+      await getOrCreateAssociatedTokenAccount(provider.connection, payer, pool.mintLp, user);
+      atas = userAtas(pool);
+      await program.methods
+        .deposit(new BN(1), new BN(800_000), new BN(200_000))
+        .accountsStrict(depositAccounts(pool, atas))
+        .rpc();
+
+      const invariant = Math.pow(800_000, 0.8) * Math.pow(200_000, 0.2);
+      const minted = await balance(atas.userAtaLp);
+      assert.isAtMost(minted, invariant - MINIMUM_LIQUIDITY);
+      assert.isAtLeast(minted, invariant - MINIMUM_LIQUIDITY - 2);
+    });
+
+    it("swaps along the weighted curve", async () => {
+      const userY = await balance(atas.userAtaY);
+      await swap(pool, mintX, new BN(1), atas);
+
+      // 10_000 in less the 30 bps fee, out = y * (1 - (x / (x + in))^(0.8 / 0.2))
+      const expected = 200_000 * (1 - Math.pow(800_000 / (800_000 + 9_970), 4));
+      const received = (await balance(atas.userAtaY)) - userY;
+      assert.isAtMost(received, expected);
+      assert.isAtLeast(received, expected - 1);
+    });
   });
 });